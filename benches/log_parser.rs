@@ -0,0 +1,63 @@
+//! Throughput of the ghunter log parser on large synthetic logs.
+//!
+//! Run with `cargo bench --features testing --bench log_parser [-- <size in MiB>]`.
+
+use std::{fs::File, path::Path, time::Instant};
+
+use ghunter4chromium_gadget_finder::{
+    log_generator::LogGenerator,
+    log_parser::{parse_log, LogReader},
+};
+
+const DEFAULT_SIZE_MIB: usize = 256;
+
+fn bench(name: &str, bytes: usize, f: impl FnOnce() -> usize) {
+    let start = Instant::now();
+    let messages = f();
+    let elapsed = start.elapsed();
+    let mib = bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "{name:<32} {messages:>10} messages in {:>8.3}s ({:>8.1} MiB/s)",
+        elapsed.as_secs_f64(),
+        mib / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let size_mib = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_SIZE_MIB);
+
+    let log = LogGenerator::new(0).log(size_mib * 1024 * 1024);
+    let dir = tempdir::TempDir::new("ghunter4chrome-bench").expect("failed to create temp dir");
+    let path = dir.path().join("synthetic.log");
+    std::fs::write(&path, &log).expect("failed to write synthetic log");
+    drop(log);
+
+    let bytes = std::fs::metadata(&path).unwrap().len() as usize;
+    println!("synthetic log: {:.1} MiB", bytes as f64 / (1024.0 * 1024.0));
+
+    bench("parse_log (owned)", bytes, || {
+        parse_log(&path).unwrap().messages.len()
+    });
+    bench("LogReader::from_slice (owned)", bytes, || {
+        let data = std::fs::read(&path).unwrap();
+        LogReader::from_slice(&data)
+            .map(|message| message.unwrap().into_owned())
+            .collect::<Vec<_>>()
+            .len()
+    });
+    bench("LogReader::from_slice (borrowed)", bytes, || {
+        let data = std::fs::read(&path).unwrap();
+        LogReader::from_slice(&data).map(Result::unwrap).count()
+    });
+    bench("LogReader::new (streaming)", bytes, || {
+        streaming_count(&path)
+    });
+}
+
+fn streaming_count(path: &Path) -> usize {
+    let file = File::open(path).unwrap();
+    LogReader::new(file).map(Result::unwrap).count()
+}
@@ -0,0 +1,38 @@
+//! Fuzzing of the ghunter log parser, which reads logs of renderers that may have
+//! crashed while writing them.
+//!
+//! Run from the repository root with `cargo +nightly fuzz run parse_log`.
+//! The seed corpus in `fuzz/corpus/parse_log` has one file per record name, along
+//! with synthetic logs written by `cargo run --bin generate_corpus` (run from `fuzz`).
+
+#![no_main]
+
+use std::io::Cursor;
+
+use ghunter4chromium_gadget_finder::log_parser::{LogMessage, LogReader};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // logs in memory are parsed without copying, while `parse_log` streams them
+    // from the file in blocks: both must see the same records
+    let borrowed = LogReader::from_slice(data)
+        .map(|message| message.map(LogMessage::into_owned))
+        .collect::<Result<Vec<_>, _>>();
+    let streamed = LogReader::new(Cursor::new(data)).collect::<Result<Vec<_>, _>>();
+    match (borrowed, streamed) {
+        (Ok(borrowed), Ok(streamed)) => assert_eq!(borrowed, streamed),
+        (borrowed, streamed) => assert_eq!(borrowed.is_ok(), streamed.is_ok()),
+    }
+
+    // every malformed record can be skipped, in the same way by both sources
+    let borrowed = LogReader::from_slice(data)
+        .lenient(true)
+        .map(|message| message.map(LogMessage::into_owned))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let streamed = LogReader::new(Cursor::new(data))
+        .lenient(true)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(borrowed, streamed);
+});
@@ -0,0 +1,32 @@
+//! Write synthetic logs to the seed corpus of the `parse_log` fuzz target, so that
+//! fuzzing starts from realistic sequences of records rather than single records.
+//!
+//! Run from the `fuzz` directory with `cargo run --bin generate_corpus`.
+
+use std::path::Path;
+
+use ghunter4chromium_gadget_finder::{log_generator::LogGenerator, log_writer::LogWriter};
+
+/// Number of synthetic logs to write
+const LOGS: u64 = 8;
+
+/// Number of records of each synthetic log, small enough for the fuzzer to
+/// mutate them efficiently
+const MESSAGES: usize = 24;
+
+fn main() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus/parse_log");
+    std::fs::create_dir_all(&corpus).expect("failed to create the corpus directory");
+
+    for seed in 0..LOGS {
+        let mut writer = LogWriter::new(Vec::new());
+        for message in LogGenerator::new(seed).messages(MESSAGES) {
+            writer
+                .write(&message)
+                .expect("writing to a vector never fails");
+        }
+        let path = corpus.join(format!("seed-generated-{seed}"));
+        std::fs::write(&path, writer.into_inner())
+            .unwrap_or_else(|error| panic!("failed to write {:?}: {}", path, error));
+    }
+}
@@ -1 +1,4 @@
 pub const POLLUTED_MARKER: &str = "0xEFFACED";
+
+/// Prefix of the property names polluted through URL payloads in the `sources` command
+pub const SOURCE_PAYLOAD_KEY_PREFIX: &str = "ghunterSource";
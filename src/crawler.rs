@@ -1,62 +1,330 @@
 use crate::{
     constants,
-    log_parser::{parse_log, SiteLog},
+    exploration::{explore, ExplorationLimits},
+    failure::{HttpStatusError, RetryPolicy},
+    interaction::{replay_steps, InteractionScript},
+    log_parser::{parse_log_since, LogDiagnostic, LogMessage, SiteLog},
+    output::LogDumper,
+    session::Session,
 };
 use anyhow::{Context, Result};
-use async_std::{stream::StreamExt, task::JoinHandle};
-use serde_json::json;
-use std::{path::PathBuf, str::FromStr, time::Duration};
-use tracing::debug;
+use async_std::{
+    future,
+    prelude::FutureExt,
+    stream::StreamExt,
+    task::{self, JoinHandle},
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+use chromiumoxide::{
+    browser::Browser,
+    cdp::browser_protocol::{
+        fetch::{self, ContinueRequestParams, EventRequestPaused, HeaderEntry},
+        inspector::{self, EventTargetCrashed},
+        network::SetCookiesParams,
+        page::{EventLifecycleEvent, SetLifecycleEventsEnabledParams},
+    },
+    error::CdpError,
+    listeners::EventStream,
+    BrowserConfig, Page,
+};
+
+/// Logs the start of every document, along with its URL and whether it is
+/// loaded in the top frame. Every record then logged from JavaScript by the
+/// document is preceded by a random identifier of the document, so that the
+/// records of documents loaded in the same renderer process can be told apart.
+const DOCUMENT_START_SCRIPT: &str = r#"
+(() => {
+  const log = __ghunter_log;
+  const id = Math.random().toString(36).slice(2);
+  globalThis.__ghunter_log = (message) => {
+    log(`IN_DOCUMENT ${id.length} ${id}`);
+    log(message);
+  };
+  const url = location.href;
+  const frame = window === window.top ? 'top' : 'child';
+  __ghunter_log(`DOCUMENT_START ${url.length} ${url} ${frame.length} ${frame}`);
+})();
+"#;
 
-use chromiumoxide::{browser::Browser, error::CdpError, BrowserConfig};
+/// How often to check the size of the ghunter log while waiting for it to go quiet
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How to decide that a page has settled after loading, i.e., that it is
+/// unlikely for more relevant events to happen.
+/// The page timeout is always the upper bound of the wait.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum SettleStrategy {
+    /// Always wait for the whole page timeout.
+    #[default]
+    Timeout,
+    /// Stop waiting as soon as the page has loaded.
+    Load,
+    /// Wait until the main frame reaches network idle.
+    #[value(name = "networkidle", alias = "network-idle")]
+    NetworkIdle,
+    /// Wait until the main frame reaches network idle and the ghunter log
+    /// has not grown for the quiet period.
+    LogQuiet,
+}
+
+/// Part of the browser that crashed during a visit
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CrashKind {
+    /// The browser process exited, or the connection to it was lost
+    Browser,
+    /// The renderer process of the page crashed
+    Renderer,
+}
+
+impl Display for CrashKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Browser => write!(f, "browser crashed"),
+            Self::Renderer => write!(f, "renderer crashed"),
+        }
+    }
+}
+
+impl std::error::Error for CrashKind {}
+
+/// A crash that happened while visiting a URL
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Crash {
+    /// The visited URL, which may include a pollution payload
+    pub url: String,
+    pub kind: CrashKind,
+    pub message: String,
+    /// Whether the visit has been retried after the crash
+    pub retried: bool,
+}
+
+/// Options of a [`Crawler`], applied to every visit
+#[derive(Clone)]
+pub struct CrawlerOptions {
+    /// How many seconds to wait at most for a page to settle
+    pub page_timeout: u64,
+    pub settle_strategy: SettleStrategy,
+    /// How long the log must stay unchanged for the page to be settled, with
+    /// [`SettleStrategy::LogQuiet`]
+    pub quiet_period: Duration,
+    /// Whether to skip malformed records of the page log instead of failing
+    pub lenient_logs: bool,
+    /// Whether to merge the logs of the other renderer processes into the page log
+    pub merge_renderer_logs: bool,
+    pub log_dumper: Option<LogDumper>,
+    /// Cookies and headers sent on every visit
+    pub session: Session,
+    /// Steps replayed in every visited page before observing it
+    pub interaction: Option<InteractionScript>,
+    /// Limits of the exploration of every visited page, if enabled
+    pub exploration: Option<ExplorationLimits>,
+    /// Whether a main navigation answered with an HTTP error status fails the visit,
+    /// instead of the page being observed like any other
+    pub fail_on_http_error: bool,
+    /// How often to retry a visit after a crash, see [`RetryPolicy::crash_retries`]
+    pub retry_policy: RetryPolicy,
+}
 
 /// Wrapper around a Browser instance that takes are of polling the handler
 /// and holds additional options.
+/// The browser is relaunched with the same config if it crashes.
 pub struct Crawler {
+    config: BrowserConfig,
     browser: Browser,
     handle: JoinHandle<()>,
-    page_timeout: u64,
+    /// Set once the handler has stopped, i.e., the connection to the browser is lost
+    handler_stopped: Arc<AtomicBool>,
+    /// Crashes that happened since they were last taken
+    crashes: Vec<Crash>,
+    /// HTTP status of the first main navigation since it was last taken
+    http_status: Option<u16>,
+    /// Malformed log records skipped since they were last taken
+    log_diagnostics: Vec<LogDiagnostic>,
+    options: CrawlerOptions,
 }
 
 impl Crawler {
     /// Create a new chromium process with the given settings
-    pub async fn new(config: BrowserConfig, page_timeout: u64) -> Result<Self> {
-        // create a `Browser` that spawns a chromium process
-        // and the handler that drives the websocket etc.
-        let (browser, mut handler) = Browser::launch(config)
-            .await
-            .context("failed to launcher browser")?;
-
-        // spawn a new task that continuously polls the handler
-        let handle = async_std::task::spawn(async move {
-            while let Some(h) = handler.next().await {
-                if h.is_err() {
-                    break;
-                }
-            }
-        });
+    pub async fn new(config: BrowserConfig, options: CrawlerOptions) -> Result<Self> {
+        let (browser, handle, handler_stopped) = launch_browser(&config).await?;
 
         Ok(Self {
+            config,
             browser,
             handle,
-            page_timeout,
+            handler_stopped,
+            crashes: Vec::new(),
+            http_status: None,
+            log_diagnostics: Vec::new(),
+            options,
         })
     }
 
     /// Stop this chromium process
     pub async fn close(mut self) -> Result<()> {
+        if !self.is_browser_alive() {
+            return Ok(());
+        }
         self.browser.close().await?;
         self.handle.await;
         Ok(())
     }
 
-    /// Visit a URL in a new tab and capture its log from ghunter
-    pub async fn visit_url(&self, url: &str, on_load_script: Option<&str>) -> Result<SiteLog> {
+    /// Crashes that happened since the last call
+    pub fn take_crashes(&mut self) -> Vec<Crash> {
+        std::mem::take(&mut self.crashes)
+    }
+
+    /// HTTP status of the main navigation of the first visit since the last call
+    pub fn take_http_status(&mut self) -> Option<u16> {
+        self.http_status.take()
+    }
+
+    /// Malformed log records skipped while parsing the logs of the visits since
+    /// the last call, in lenient mode or because they were truncated
+    pub fn take_log_diagnostics(&mut self) -> Vec<LogDiagnostic> {
+        std::mem::take(&mut self.log_diagnostics)
+    }
+
+    fn is_browser_alive(&mut self) -> bool {
+        !self.handler_stopped.load(Ordering::Relaxed) && matches!(self.browser.try_wait(), Ok(None))
+    }
+
+    /// Kill what remains of the browser and launch a new one with the same config
+    async fn relaunch(&mut self) -> Result<()> {
+        let _ = self.browser.kill().await;
+        let (browser, handle, handler_stopped) = launch_browser(&self.config).await?;
+        self.browser = browser;
+        self.handle = handle;
+        self.handler_stopped = handler_stopped;
+        Ok(())
+    }
+
+    /// Visit a URL in a new tab and capture its log from ghunter.
+    ///
+    /// If the browser or the renderer crashes during the visit, the crash is recorded
+    /// (see [`Crawler::take_crashes`]), the browser is relaunched if needed, and the
+    /// visit is retried according to the retry policy.
+    pub async fn visit_url(&mut self, url: &str, on_load_script: Option<&str>) -> Result<SiteLog> {
+        if !self.is_browser_alive() {
+            warn!("browser is not running anymore, relaunching it");
+            self.relaunch().await?;
+        }
+
+        let mut retries = 0;
+        loop {
+            let error = match self.try_visit_url(url, on_load_script).await {
+                Ok(log) => return Ok(log),
+                Err(error) => error,
+            };
+            let kind = if !self.is_browser_alive() {
+                CrashKind::Browser
+            } else if let Some(kind) = error.downcast_ref::<CrashKind>() {
+                *kind
+            } else {
+                return Err(error);
+            };
+
+            let retried = self.options.retry_policy.retries_crash(retries);
+            warn!(
+                url = url,
+                retried = retried,
+                "{} while visiting website: {:#}",
+                kind,
+                error
+            );
+            self.crashes.push(Crash {
+                url: url.to_string(),
+                kind,
+                message: format!("{:#}", error),
+                retried,
+            });
+            if kind == CrashKind::Browser {
+                self.relaunch()
+                    .await
+                    .context("failed to relaunch the browser after a crash")?;
+            }
+            if !retried {
+                return Err(match kind {
+                    CrashKind::Browser => error.context(kind),
+                    CrashKind::Renderer => error,
+                });
+            }
+            task::sleep(self.options.retry_policy.delay(retries)).await;
+            retries += 1;
+        }
+    }
+
+    async fn try_visit_url(&mut self, url: &str, on_load_script: Option<&str>) -> Result<SiteLog> {
         let page = self.browser.new_page("about:blank").await?;
+        let observed = self.observe_page(&page, url, on_load_script).await;
+        // the tab is closed on every path, so that failed visits do not pile up tabs
+        let closed = page.close().await;
+        let (log_path, log_sizes) = observed?;
+        closed?;
+
+        let log = self.read_logs(&log_path, log_sizes.as_ref())?;
+
+        let unknown_records = log
+            .messages
+            .iter()
+            .filter(|msg| matches!(msg, LogMessage::Unknown { .. }))
+            .count();
+        if unknown_records > 0 {
+            debug!("page log contains {} unknown records", unknown_records);
+        }
+
+        for diagnostic in &log.diagnostics {
+            warn!(
+                log_file = ?diagnostic.log_file,
+                offset = diagnostic.offset,
+                record = diagnostic.record,
+                "skipped malformed log record: {}",
+                diagnostic.message
+            );
+        }
+        self.log_diagnostics.extend(log.diagnostics.iter().cloned());
+
+        debug!("page log: {:#?}", log);
+
+        // the dump is only a by-product of the visit, so it does not fail it
+        if let Some(log_dumper) = &self.options.log_dumper {
+            if let Err(error) = log_dumper.dump(url, &log) {
+                warn!(url = url, "failed while dumping page log {:?}", error);
+            }
+        }
+
+        Ok(log)
+    }
+
+    /// Set up the page, load the URL in it and wait for it to settle, returning
+    /// the path of the page log along with the sizes of the logs before the visit
+    /// if the logs of other renderer processes are merged
+    async fn observe_page(
+        &mut self,
+        page: &Page,
+        url: &str,
+        on_load_script: Option<&str>,
+    ) -> Result<(PathBuf, Option<BTreeMap<PathBuf, u64>>)> {
         page.wait_for_navigation().await?;
         page.enable_stealth_mode_with_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/130.0.0.0 Safari/537.36").await?;
-        page.evaluate_on_new_document("__ghunter_log('DOCUMENT_LOAD');")
-            .await?;
+        page.evaluate_on_new_document(DOCUMENT_START_SCRIPT).await?;
         if let Some(on_load_script) = on_load_script {
             page.evaluate_on_new_document(on_load_script).await?;
         }
@@ -64,47 +332,426 @@ impl Crawler {
             .evaluate_function("__ghunter_getLogPath")
             .await?
             .into_value()?;
+        let log_path = PathBuf::from_str(&log_path)
+            .with_context(|| format!("failed while opening log file at {}", &log_path))?;
+        // cross-site iframes and popups run in other renderer processes, each with
+        // its own log, so remember where every log ends before the visit
+        let log_sizes = if self.options.merge_renderer_logs {
+            Some(list_log_files(&log_path)?)
+        } else {
+            None
+        };
+
+        page.execute(SetLifecycleEventsEnabledParams::new(true))
+            .await?;
+        page.execute(inspector::EnableParams::default()).await?;
+        let mut crashes = page.event_listener::<EventTargetCrashed>().await?;
+        let lifecycle_events = page.event_listener::<EventLifecycleEvent>().await?;
 
-        match page.goto(url).await.map(|_| ()) {
-            // some pages get stuck on goto for some reason, even though they loaded fine
-            Err(CdpError::Timeout) => {}
-            result => result?,
+        if !self.options.session.cookies.is_empty() {
+            page.execute(SetCookiesParams::new(self.options.session.cookies.clone()))
+                .await?;
         }
-        page.wait_for_navigation().await?;
+        // extra headers only apply to some domains, so every request is paused to add them
+        let paused_requests = if self.options.session.headers.is_empty() {
+            None
+        } else {
+            let paused_requests = page.event_listener::<EventRequestPaused>().await?;
+            page.execute(fetch::EnableParams::default()).await?;
+            Some(paused_requests)
+        };
+        let add_headers = async {
+            if let Some(paused_requests) = paused_requests {
+                add_session_headers(page, paused_requests, &self.options.session).await;
+            }
+            future::pending().await
+        };
 
-        // wait for 5 seconds allow for potential events to happen
-        std::thread::sleep(Duration::from_secs(self.page_timeout));
+        let (steps_before, steps_after) = match &self.options.interaction {
+            Some(interaction) => interaction.split_at_navigation(),
+            None => (&[][..], &[][..]),
+        };
+        let visit = async {
+            replay_steps(page, steps_before)
+                .await
+                .context("failed to replay the interaction script")?;
+            match page.goto(url).await.map(|_| ()) {
+                // some pages get stuck on goto for some reason, even though they loaded fine
+                Err(CdpError::Timeout) => {}
+                result => result?,
+            }
+            let navigation = page.wait_for_navigation_response().await?;
+            let http_status = navigation
+                .as_ref()
+                .and_then(|request| request.response.as_ref())
+                .and_then(|response| u16::try_from(response.status).ok());
+            if self.options.fail_on_http_error && matches!(http_status, Some(400..)) {
+                return Ok(http_status);
+            }
+            // the page is observed in the state it is in after the interaction
+            replay_steps(page, steps_after)
+                .await
+                .context("failed to replay the interaction script")?;
+            if let Some(limits) = self.options.exploration {
+                if let Err(error) = explore(page, limits).await {
+                    debug!("exploration of the page failed: {:#}", error);
+                }
+            }
 
-        page.close().await?;
+            // wait for the page to settle, allowing for potential events to happen
+            let page_timeout = Duration::from_secs(self.options.page_timeout);
+            let settle = async {
+                self.settle(page, lifecycle_events, &log_path).await;
+                Ok(())
+            };
+            let crash = async {
+                match crashes.next().await {
+                    Some(_) => Err(CrashKind::Renderer),
+                    None => future::pending().await,
+                }
+            };
+            match future::timeout(page_timeout, settle.race(crash)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(crash)) => return Err(crash.into()),
+                Err(_) => debug!(
+                    strategy = ?self.options.settle_strategy,
+                    "page did not settle before the page timeout"
+                ),
+            }
+            Ok::<_, anyhow::Error>(http_status)
+        };
 
-        let log_path = PathBuf::from_str(&log_path)
-            .with_context(|| format!("failed while opening log file at {}", &log_path))?;
-        let log = parse_log(&log_path)
-            .with_context(|| format!("failed while parsing log file at {:?}", &log_path))?;
+        let http_status = visit.race(add_headers).await?;
+        if self.http_status.is_none() {
+            self.http_status = http_status;
+        }
+        if let (true, Some(status @ 400..)) = (self.options.fail_on_http_error, http_status) {
+            return Err(HttpStatusError(status).into());
+        }
 
-        debug!("page log: {:#?}", log);
+        Ok((log_path, log_sizes))
+    }
 
-        Ok(log)
+    /// Parse the log of the page. If the sizes of the log files before the visit are
+    /// given, the logs of the other renderer processes that grew during the visit
+    /// are parsed as well and merged after the log of the page.
+    ///
+    /// The other logs are always parsed leniently, and skipped if they cannot be
+    /// parsed at all, as they may be written to while they are read.
+    fn read_logs(
+        &self,
+        log_path: &Path,
+        log_sizes: Option<&BTreeMap<PathBuf, u64>>,
+    ) -> Result<SiteLog> {
+        let log = parse_log_since(log_path, 0, self.options.lenient_logs)
+            .with_context(|| format!("failed while parsing log file at {:?}", log_path))?;
+        let Some(log_sizes) = log_sizes else {
+            return Ok(log);
+        };
+
+        let mut logs = vec![log];
+        for (path, size) in list_log_files(log_path)? {
+            let previous_size = log_sizes.get(&path).copied().unwrap_or(0);
+            if path == log_path || size <= previous_size {
+                continue;
+            }
+            debug!(log_file = ?path, "merging log of another renderer process");
+            match parse_log_since(&path, previous_size, true) {
+                Ok(log) => logs.push(log),
+                Err(error) => warn!(
+                    log_file = ?path,
+                    "skipping log of another renderer process: {}",
+                    error
+                ),
+            }
+        }
+
+        Ok(SiteLog::merge(logs))
     }
+
+    /// Wait until the page is considered settled according to the settle strategy.
+    /// This does not enforce the page timeout, so the caller must do so.
+    async fn settle(
+        &self,
+        page: &Page,
+        mut lifecycle_events: EventStream<EventLifecycleEvent>,
+        log_path: &Path,
+    ) {
+        match self.options.settle_strategy {
+            SettleStrategy::Timeout => future::pending().await,
+            SettleStrategy::Load => {}
+            SettleStrategy::NetworkIdle => {
+                wait_for_network_idle(page, &mut lifecycle_events).await;
+            }
+            SettleStrategy::LogQuiet => {
+                wait_for_network_idle(page, &mut lifecycle_events).await;
+                wait_for_quiet_log(log_path, self.options.quiet_period).await;
+            }
+        }
+    }
+}
+
+/// Wait for the network idle lifecycle event of the current document of the main frame.
+async fn wait_for_network_idle(
+    page: &Page,
+    lifecycle_events: &mut EventStream<EventLifecycleEvent>,
+) {
+    let Ok(Some(main_frame)) = page.mainframe().await else {
+        return future::pending().await;
+    };
+
+    // only consider events of the document created by the navigation, ignoring
+    // leftovers from the initial about:blank page
+    let mut loader_id = None;
+    while let Some(event) = lifecycle_events.next().await {
+        if event.frame_id != main_frame {
+            continue;
+        }
+        match event.name.as_str() {
+            "init" => loader_id = Some(event.loader_id.clone()),
+            "networkIdle" if loader_id.as_ref() == Some(&event.loader_id) => return,
+            _ => {}
+        }
+    }
+
+    // the page has been closed, so there will be no more events
+    future::pending().await
+}
+
+/// Continue every paused request of the page, adding the headers of the session
+/// that apply to its domain
+async fn add_session_headers(
+    page: &Page,
+    mut paused_requests: EventStream<EventRequestPaused>,
+    session: &Session,
+) {
+    while let Some(paused) = paused_requests.next().await {
+        let mut params = ContinueRequestParams::new(paused.request_id.clone());
+        let extra_headers = session.headers_for(&paused.request.url);
+        if !extra_headers.is_empty() {
+            let mut headers: Vec<HeaderEntry> = paused
+                .request
+                .headers
+                .inner()
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(name, _)| {
+                    !extra_headers
+                        .iter()
+                        .any(|header| header.name.eq_ignore_ascii_case(name))
+                })
+                .map(|(name, value)| HeaderEntry::new(name, value.as_str().unwrap_or_default()))
+                .collect();
+            headers.extend(extra_headers);
+            params.headers = Some(headers);
+        }
+        if let Err(error) = page.execute(params).await {
+            debug!("failed to continue paused request: {:?}", error);
+        }
+    }
+}
+
+/// Launch a chromium process, along with a task polling its handler.
+/// The returned flag is set once the handler has stopped.
+async fn launch_browser(
+    config: &BrowserConfig,
+) -> Result<(Browser, JoinHandle<()>, Arc<AtomicBool>)> {
+    // create a `Browser` that spawns a chromium process
+    // and the handler that drives the websocket etc.
+    let (browser, mut handler) = Browser::launch(config.clone())
+        .await
+        .context("failed to launcher browser")?;
+
+    // spawn a new task that continuously polls the handler
+    let handler_stopped = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::clone(&handler_stopped);
+    let handle = async_std::task::spawn(async move {
+        while let Some(h) = handler.next().await {
+            if let Err(error) = h {
+                debug!("browser handler stopped: {:?}", error);
+                break;
+            }
+        }
+        stopped.store(true, Ordering::Relaxed);
+    });
+
+    Ok((browser, handle, handler_stopped))
+}
+
+/// Wait until the size of the log file has not changed for the given period.
+async fn wait_for_quiet_log(log_path: &Path, quiet_period: Duration) {
+    let log_size = || std::fs::metadata(log_path).map(|m| m.len()).unwrap_or(0);
+
+    let mut last_size = log_size();
+    let mut quiet_for = Duration::ZERO;
+    while quiet_for < quiet_period {
+        async_std::task::sleep(LOG_POLL_INTERVAL).await;
+        let size = log_size();
+        if size == last_size {
+            quiet_for += LOG_POLL_INTERVAL;
+        } else {
+            last_size = size;
+            quiet_for = Duration::ZERO;
+        }
+    }
+}
+
+/// Sizes of the log files in the directory of the given log, i.e., the logs of
+/// every renderer process
+fn list_log_files(log_path: &Path) -> Result<BTreeMap<PathBuf, u64>> {
+    let log_dir = log_path
+        .parent()
+        .with_context(|| format!("log file at {:?} has no parent directory", log_path))?;
+    let entries = std::fs::read_dir(log_dir)
+        .with_context(|| format!("failed while listing log directory {:?}", log_dir))?;
+
+    let mut log_files = BTreeMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension() != log_path.extension() {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            log_files.insert(path, metadata.len());
+        }
+    }
+
+    Ok(log_files)
+}
+
+/// Value assigned to a polluted property
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum PollutionValue {
+    /// The taint marker, returned from a getter that logs every read
+    Marker,
+    /// A fixed value, installed verbatim
+    Value(Value),
+    /// An array containing the taint marker
+    TaintedArray,
+    /// An object with the taint marker as both key and value
+    TaintedObject,
+    /// A function returning the given value, or the taint marker if there is none
+    Function(Option<Value>),
+}
+
+impl PollutionValue {
+    /// Kind and (optional) value of this pollution value, as understood by the polluting script
+    fn script_kind_and_value(&self) -> (&'static str, Option<&Value>) {
+        match self {
+            Self::Marker => ("marker", None),
+            Self::Value(value) => ("value", Some(value)),
+            Self::TaintedArray => ("array", None),
+            Self::TaintedObject => ("object", None),
+            Self::Function(value) => ("function", value.as_ref()),
+        }
+    }
+}
+
+/// Serialized origin of the URL (e.g., `https://example.com:8080`), if it is not opaque
+pub fn url_origin(url: &str) -> Option<String> {
+    let origin = url::Url::parse(url).ok()?.origin();
+    origin.is_tuple().then(|| origin.ascii_serialization())
 }
 
 /// Generate a script, to be loaded at the start of each frame,
 /// that pollutes the given properties in the object prototype,
 /// setting their values to a taint tracker (with some additional data
 /// dynamically added through a getter).
-pub fn gen_polluting_script(properties_to_pollute: &[(String, Option<String>)]) -> String {
+/// Properties with a typed value are either set verbatim (fixed values) or
+/// to a container whose leaves are taint trackers.
+///
+/// If `use_proxy` is set, the taint tracker is wrapped in a `Proxy` that logs
+/// every property read, call, coercion and `in` check performed on it,
+/// recursively, so that the full access path of a gadget can be recovered.
+/// Reads of `then` and `toJSON` are logged but return `undefined`, so that the
+/// proxy is neither awaited forever nor serialized through a proxy.
+pub fn gen_polluting_script(
+    properties_to_pollute: &[(String, PollutionValue)],
+    use_proxy: bool,
+) -> String {
     let polluted_marker = json!(constants::POLLUTED_MARKER);
-    let properties_json = json!(properties_to_pollute);
+    let properties_json: Vec<_> = properties_to_pollute
+        .iter()
+        .map(|(p, v)| {
+            let (kind, value) = v.script_kind_and_value();
+            json!([p, kind, value])
+        })
+        .collect();
+    let properties_json = json!(properties_json);
+    let use_proxy = json!(use_proxy);
 
     format!(
         r#"
 const __ghunter = {{
   pollutedMarker: {}, // <-- this is dynamic
   propertiesToPollute: {}, // <-- this is dynamic
+  useProxy: {}, // <-- this is dynamic
 }};
 
-__ghunter.propertiesToPollute.forEach(([p, v], i) => {{
-  if (v !== null && v !== undefined) {{
+__ghunter.logProxyAccess = (type, path, value) => {{
+  try {{
+    throw new Error();
+  }} catch (error) {{
+    const stacktrace = error.stack;
+    __ghunter_log(`${{type}} ${{path.length}} ${{path}} ${{value.length}} ${{value}} ${{stacktrace.length}} ${{stacktrace}}`);
+  }}
+}};
+
+// keys looked up by the engine to decide how to treat a value
+__ghunter.protocolKeys = ['then', 'toJSON'];
+
+__ghunter.makeProxy = (path, value) => {{
+  const coerce = (coercionPath) => {{
+    __ghunter.logProxyAccess('PROXY_COERCE', coercionPath, value);
+    return value;
+  }};
+  // the handler must not inherit from the (polluted) Object.prototype, otherwise
+  // polluting a property named like a trap would change the behaviour of the proxy
+  const handler = Object.assign(Object.create(null), {{
+    get(target, key) {{
+      if (key === Symbol.toPrimitive) {{
+        return (hint) => coerce(`${{path}}[Symbol.toPrimitive](${{hint}})`);
+      }}
+      if (typeof key === 'symbol') {{
+        return Reflect.get(target, key);
+      }}
+      if (key === 'toString' || key === 'valueOf') {{
+        return () => coerce(`${{path}}.${{key}}()`);
+      }}
+      const childPath = `${{path}}.${{key}}`;
+      __ghunter.logProxyAccess('PROXY_GET', childPath, value);
+      // keys probed by the engine must not look implemented, otherwise the proxy
+      // would be a thenable that never settles when awaited
+      if (__ghunter.protocolKeys.includes(key)) {{
+        return undefined;
+      }}
+      return __ghunter.makeProxy(childPath, value);
+    }},
+    apply(target, thisArg, args) {{
+      const childPath = `${{path}}()`;
+      __ghunter.logProxyAccess('PROXY_CALL', childPath, value);
+      return __ghunter.makeProxy(childPath, value);
+    }},
+    construct(target, args) {{
+      const childPath = `new ${{path}}()`;
+      __ghunter.logProxyAccess('PROXY_CONSTRUCT', childPath, value);
+      return __ghunter.makeProxy(childPath, value);
+    }},
+    has(target, key) {{
+      __ghunter.logProxyAccess('PROXY_HAS', `${{path}}.${{String(key)}}`, value);
+      return Reflect.has(target, key);
+    }},
+  }});
+  // use a function as target so that the proxy can be called and constructed
+  return new Proxy(function () {{}}, handler);
+}};
+
+__ghunter.propertiesToPollute.forEach(([p, kind, v], i) => {{
+  if (kind === 'value') {{
     Object.prototype[p] = v;
     return;
   }}
@@ -124,7 +771,19 @@ __ghunter.propertiesToPollute.forEach(([p, v], i) => {{
           __ghunter_log(`PROTOTYPE_GET ${{p.length}} ${{p}} ${{returnValue.length}} ${{returnValue}} ${{stacktrace.length}} ${{stacktrace}}`);
         }}
 
-        // TODO: support proxy
+        switch (kind) {{
+          case 'array':
+            return [returnValue];
+          case 'object':
+            return {{ [returnValue]: returnValue }};
+          case 'function':
+            return function () {{
+              return v !== null ? v : returnValue;
+            }};
+        }}
+        if (__ghunter.useProxy) {{
+          return __ghunter.makeProxy(p, returnValue);
+        }}
         return returnValue;
       }},
       set: function(newValue) {{
@@ -144,6 +803,6 @@ __ghunter.propertiesToPollute.forEach(([p, v], i) => {{
     }}
   );
 }})"#,
-        polluted_marker, properties_json
+        polluted_marker, properties_json, use_proxy
     )
 }
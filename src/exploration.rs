@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_std::{future, task};
+use chromiumoxide::{cdp::browser_protocol::input::InsertTextParams, layout::Point, Page};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::{
+    constants::POLLUTED_MARKER,
+    crawler::url_origin,
+    interaction::{click, find_element, Selectors},
+};
+
+/// How long to wait after every action, giving the page time to react
+const ACTION_DELAY: Duration = Duration::from_millis(200);
+
+/// How many times to scroll down by a screen at most
+const MAX_SCROLLS: usize = 10;
+
+/// Prevents the page from leaving its origin or opening new windows while it is
+/// explored. Links and forms to other origins are blocked after the page's own
+/// handlers had a chance to run.
+const NAVIGATION_GUARD_FUNCTION: &str = r#"
+() => {
+  if (window.__ghunterNavigationGuard) return;
+  window.__ghunterNavigationGuard = true;
+  const isCrossOrigin = (url) => {
+    try {
+      return new URL(url, location.href).origin !== location.origin;
+    } catch {
+      return true;
+    }
+  };
+  document.addEventListener('click', (event) => {
+    const link = event.target instanceof Element && event.target.closest('a[href], area[href]');
+    if (link && (isCrossOrigin(link.href) || link.target === '_blank' || link.hasAttribute('download'))) {
+      event.preventDefault();
+    }
+  }, true);
+  document.addEventListener('submit', (event) => {
+    if (isCrossOrigin(event.target.action) || event.target.target === '_blank') {
+      event.preventDefault();
+    }
+  }, true);
+  window.open = () => null;
+}
+"#;
+
+/// Marks the visible interactive elements of the page with an identifier and
+/// lists them in document order, telling apart those that can be filled in. The
+/// identifiers of a previous collection are removed first.
+const COLLECT_ELEMENTS_FUNCTION: &str = r#"
+() => {
+  for (const element of document.querySelectorAll('[data-ghunter-explore]')) {
+    element.removeAttribute('data-ghunter-explore');
+  }
+  const selector = 'a[href], button, input, textarea, summary, [role=button], [role=link], [role=tab], [role=menuitem], [onclick], [contenteditable=""], [contenteditable=true]';
+  const fillableTypes = ['text', 'search', 'email', 'url', 'tel', 'password'];
+  const skippedTypes = ['file', 'hidden', 'reset', 'image'];
+  const elements = [];
+  let id = 0;
+  for (const element of document.querySelectorAll(selector)) {
+    if (element.disabled) continue;
+    if (element.tagName === 'INPUT' && skippedTypes.includes(element.type)) continue;
+    const rect = element.getBoundingClientRect();
+    const style = getComputedStyle(element);
+    if (rect.width === 0 || rect.height === 0 || style.visibility === 'hidden') continue;
+
+    element.setAttribute('data-ghunter-explore', id);
+    const fill = element.tagName === 'TEXTAREA'
+      || element.isContentEditable
+      || (element.tagName === 'INPUT' && fillableTypes.includes(element.type));
+    elements.push({ id: id++, fill });
+  }
+  return elements;
+}
+"#;
+
+/// Limits of the exploration phase of a visit
+#[derive(Clone, Copy, Debug)]
+pub struct ExplorationLimits {
+    /// How many scrolls, clicks and inputs to perform at most
+    pub max_actions: usize,
+    pub timeout: Duration,
+}
+
+/// Interactive element listed by [`COLLECT_ELEMENTS_FUNCTION`]
+#[derive(Deserialize)]
+struct Candidate {
+    id: usize,
+    /// The element is an input, filled with the taint marker instead of clicked
+    fill: bool,
+}
+
+/// Simulate a user going through the page to trigger code that only runs on
+/// interaction: scroll down the page, then hover and click its visible interactive
+/// elements and fill its inputs with the taint marker.
+///
+/// The page is not allowed to leave its origin. If it does anyway (e.g., through a
+/// script setting `location`), it is loaded again and the exploration stops. When
+/// it navigates within its origin, the elements of the new page are collected and
+/// explored in turn.
+pub async fn explore(page: &Page, limits: ExplorationLimits) -> Result<()> {
+    let Some(start_url) = page.url().await? else {
+        return Ok(());
+    };
+    let origin = url_origin(&start_url);
+
+    let exploration = async {
+        let mut actions = 0;
+        for _ in 0..MAX_SCROLLS.min(limits.max_actions) {
+            actions += 1;
+            let at_bottom: bool = page
+                .evaluate_function(
+                    "() => { window.scrollBy(0, window.innerHeight); \
+                     return window.innerHeight + window.scrollY >= document.documentElement.scrollHeight; }",
+                )
+                .await?
+                .into_value()?;
+            task::sleep(ACTION_DELAY).await;
+            if at_bottom {
+                break;
+            }
+        }
+        page.evaluate_function("() => window.scrollTo(0, 0)")
+            .await?;
+
+        let mut explored_url = Some(start_url.clone());
+        'documents: while actions < limits.max_actions {
+            page.evaluate_function(NAVIGATION_GUARD_FUNCTION).await?;
+            let candidates: Vec<Candidate> = page
+                .evaluate_function(COLLECT_ELEMENTS_FUNCTION)
+                .await?
+                .into_value()?;
+            debug!("exploring {} interactive elements", candidates.len());
+
+            for candidate in candidates {
+                if actions >= limits.max_actions {
+                    break;
+                }
+                actions += 1;
+
+                let selectors =
+                    Selectors::Single(format!("[data-ghunter-explore=\"{}\"]", candidate.id));
+                if candidate.fill {
+                    let marker = json!(POLLUTED_MARKER);
+                    let Some(element) = find_element(page, &selectors, "change", &marker).await?
+                    else {
+                        continue;
+                    };
+                    if !element.done {
+                        page.execute(InsertTextParams::new(POLLUTED_MARKER)).await?;
+                        page.evaluate_function(
+                            "() => document.activeElement?.dispatchEvent(new Event('change', { bubbles: true }))",
+                        )
+                        .await?;
+                    }
+                } else {
+                    let Some(element) =
+                        find_element(page, &selectors, "click", &Value::Null).await?
+                    else {
+                        continue;
+                    };
+                    page.move_mouse(Point::new(element.x, element.y)).await?;
+                    task::sleep(ACTION_DELAY).await;
+                    click(page, &element, 1).await?;
+                }
+                task::sleep(ACTION_DELAY).await;
+
+                let current_url = page.url().await?;
+                if current_url.as_deref().and_then(url_origin) != origin {
+                    debug!(
+                        url = current_url,
+                        "page left its origin during the exploration, loading it again"
+                    );
+                    page.goto(start_url.as_str()).await?;
+                    page.wait_for_navigation().await?;
+                    return Ok(());
+                }
+                // the identifiers of the remaining candidates are gone from a new
+                // document, which is also missing the guard
+                let new_document: bool = page
+                    .evaluate_function("() => !window.__ghunterNavigationGuard")
+                    .await?
+                    .into_value()?;
+                if new_document || current_url != explored_url {
+                    debug!(url = current_url, "page navigated during the exploration");
+                    explored_url = current_url;
+                    continue 'documents;
+                }
+            }
+            break;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    match future::timeout(limits.timeout, exploration).await {
+        Ok(result) => result,
+        Err(_) => {
+            debug!("exploration did not finish before its timeout");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::{fmt::Display, time::Duration};
+
+use chromiumoxide::error::CdpError;
+use serde::{Deserialize, Serialize};
+
+use crate::{crawler::CrashKind, log_parser::LogError};
+
+/// Upper bound of the delay between two attempts at processing a URL
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Why processing a URL failed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The host name could not be resolved
+    Dns,
+    /// The TLS handshake failed or the certificate was rejected
+    Tls,
+    /// The navigation or a request to the browser timed out
+    Timeout,
+    /// The connection failed for another reason (e.g., refused or reset)
+    Network,
+    /// The main navigation was answered with a 4xx status
+    HttpClientError,
+    /// The main navigation was answered with a 5xx status
+    HttpServerError,
+    RendererCrash,
+    BrowserCrash,
+    /// The ghunter log of the page could not be parsed
+    LogParse,
+    Other,
+}
+
+impl FailureKind {
+    /// Whether another attempt may succeed without anything changing on our side
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            Self::Timeout
+                | Self::Network
+                | Self::HttpServerError
+                | Self::RendererCrash
+                | Self::BrowserCrash
+        )
+    }
+
+    /// Classify a Chrome network error, e.g., `net::ERR_NAME_NOT_RESOLVED`
+    fn from_net_error(error: &str) -> Option<Self> {
+        let code = error.strip_prefix("net::ERR_")?;
+        let kind = if code.starts_with("NAME_") || code.starts_with("DNS_") {
+            Self::Dns
+        } else if code.starts_with("CERT_")
+            || code.starts_with("SSL_")
+            || code.contains("_SSL_")
+            || code.starts_with("TLS_")
+        {
+            Self::Tls
+        } else if code.ends_with("TIMED_OUT") {
+            Self::Timeout
+        } else {
+            Self::Network
+        };
+        Some(kind)
+    }
+}
+
+/// The main navigation of a visit was answered with an HTTP error status
+#[derive(Debug, Clone, Copy)]
+pub struct HttpStatusError(pub u16);
+
+impl Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "page responded with HTTP status {}", self.0)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// A failed attempt at processing a URL
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Failure {
+    pub kind: FailureKind,
+    /// Set for HTTP errors
+    pub http_status: Option<u16>,
+    pub message: String,
+}
+
+impl Failure {
+    /// Classify an error returned while processing a URL
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let mut http_status = None;
+        let kind = if let Some(crash) = error.downcast_ref::<CrashKind>() {
+            match crash {
+                CrashKind::Browser => FailureKind::BrowserCrash,
+                CrashKind::Renderer => FailureKind::RendererCrash,
+            }
+        } else if let Some(HttpStatusError(status)) = error.downcast_ref::<HttpStatusError>() {
+            http_status = Some(*status);
+            match status {
+                500.. => FailureKind::HttpServerError,
+                _ => FailureKind::HttpClientError,
+            }
+        } else if error.downcast_ref::<LogError>().is_some() {
+            FailureKind::LogParse
+        } else {
+            match error.downcast_ref::<CdpError>() {
+                Some(CdpError::Timeout) => FailureKind::Timeout,
+                Some(CdpError::ChromeMessage(message)) => {
+                    FailureKind::from_net_error(message).unwrap_or(FailureKind::Other)
+                }
+                _ => FailureKind::Other,
+            }
+        };
+
+        Self {
+            kind,
+            http_status,
+            message: format!("{:#}", error),
+        }
+    }
+
+    /// Whether another attempt may succeed. Rate limited requests are worth
+    /// retrying even though they are client errors.
+    pub fn is_transient(&self) -> bool {
+        self.kind.is_transient() || self.http_status == Some(429)
+    }
+
+    /// Whether processing the URL should be attempted again after this failure.
+    /// Crashes are not, as the crawler already retried the visit they happened in
+    /// according to the same policy.
+    pub fn should_retry(&self) -> bool {
+        self.is_transient()
+            && !matches!(
+                self.kind,
+                FailureKind::RendererCrash | FailureKind::BrowserCrash
+            )
+    }
+}
+
+/// How often to retry processing a URL after a transient failure
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// How many times to retry a URL after a transient failure
+    pub retries: usize,
+    /// How many times to retry a visit during which the browser or the renderer
+    /// crashed, independently of `retries`
+    pub crash_retries: usize,
+    /// Delay before the first retry, doubled for every following one
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Whether to retry a visit that crashed, after the given number of retries
+    pub fn retries_crash(&self, retries: usize) -> bool {
+        retries < self.crash_retries
+    }
+
+    /// Delay before the given retry, starting at 0
+    pub fn delay(&self, retry: usize) -> Duration {
+        let factor = 1u32.checked_shl(retry as u32).unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(MAX_RETRY_BACKOFF)
+            .min(MAX_RETRY_BACKOFF)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn net_errors() {
+        let kind = FailureKind::from_net_error;
+        assert_eq!(kind("net::ERR_NAME_NOT_RESOLVED"), Some(FailureKind::Dns));
+        assert_eq!(kind("net::ERR_DNS_TIMED_OUT"), Some(FailureKind::Dns));
+        assert_eq!(
+            kind("net::ERR_CERT_AUTHORITY_INVALID"),
+            Some(FailureKind::Tls)
+        );
+        assert_eq!(kind("net::ERR_SSL_PROTOCOL_ERROR"), Some(FailureKind::Tls));
+        assert_eq!(
+            kind("net::ERR_BAD_SSL_CLIENT_AUTH_CERT"),
+            Some(FailureKind::Tls)
+        );
+        assert_eq!(
+            kind("net::ERR_CONNECTION_TIMED_OUT"),
+            Some(FailureKind::Timeout)
+        );
+        assert_eq!(
+            kind("net::ERR_CONNECTION_REFUSED"),
+            Some(FailureKind::Network)
+        );
+        assert_eq!(
+            kind("net::ERR_CONNECTION_RESET"),
+            Some(FailureKind::Network)
+        );
+        assert_eq!(kind("Cannot navigate to invalid URL"), None);
+    }
+
+    #[test]
+    fn errors_are_classified() {
+        let failure = Failure::from_error(&HttpStatusError(503).into());
+        assert_eq!(failure.kind, FailureKind::HttpServerError);
+        assert_eq!(failure.http_status, Some(503));
+        assert!(failure.should_retry());
+
+        let failure = Failure::from_error(&HttpStatusError(404).into());
+        assert_eq!(failure.kind, FailureKind::HttpClientError);
+        assert!(!failure.is_transient());
+
+        let failure = Failure::from_error(&HttpStatusError(429).into());
+        assert_eq!(failure.kind, FailureKind::HttpClientError);
+        assert!(failure.should_retry());
+
+        let error = anyhow::Error::from(CdpError::ChromeMessage(
+            "net::ERR_NAME_NOT_RESOLVED".to_owned(),
+        ));
+        assert_eq!(Failure::from_error(&error).kind, FailureKind::Dns);
+
+        let error = anyhow::Error::from(CrashKind::Renderer).context("visit failed");
+        let failure = Failure::from_error(&error);
+        assert_eq!(failure.kind, FailureKind::RendererCrash);
+        assert!(failure.is_transient());
+        assert!(!failure.should_retry());
+
+        let failure = Failure::from_error(&anyhow::anyhow!("something else"));
+        assert_eq!(failure.kind, FailureKind::Other);
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_limit() {
+        let policy = RetryPolicy {
+            retries: 10,
+            crash_retries: 1,
+            backoff: Duration::from_millis(500),
+        };
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(7), MAX_RETRY_BACKOFF);
+        assert_eq!(policy.delay(40), MAX_RETRY_BACKOFF);
+        assert_eq!(policy.delay(usize::MAX), MAX_RETRY_BACKOFF);
+
+        let policy = RetryPolicy {
+            retries: 1,
+            crash_retries: 1,
+            backoff: Duration::ZERO,
+        };
+        assert_eq!(policy.delay(5), Duration::ZERO);
+    }
+}
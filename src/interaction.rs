@@ -0,0 +1,679 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use chromiumoxide::{
+    cdp::browser_protocol::{
+        emulation::SetDeviceMetricsOverrideParams,
+        input::{
+            DispatchKeyEventParams, DispatchKeyEventType, DispatchMouseEventParams,
+            DispatchMouseEventType, InsertTextParams, MouseButton,
+        },
+    },
+    keys,
+    layout::Point,
+    Page,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// How long a step waits for its element (or expression) if it does not set a timeout
+const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often to look for the element of a step while waiting for it
+const STEP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Finds the first element matching one of the given selectors, scrolls it into
+/// view and returns its center. Every selector is a chain of selectors, each one
+/// looked up in the shadow root (or the subtree) of the element matched by the
+/// previous one. Selectors are CSS selectors, or XPath, text and shadow piercing
+/// selectors with the prefixes of the DevTools Recorder (`xpath/`, `text/` and
+/// `pierce/`). ARIA selectors are not supported and never match.
+///
+/// For the `change` action, the element is focused and its contents selected, so
+/// that typing replaces them. Select elements get their value set directly.
+/// For the `scroll` action, the element is scrolled to the given position.
+const FIND_ELEMENT_FUNCTION: &str = r#"
+(selectors, action, value) => {
+  const pierce = (root, selector) => {
+    const found = root.querySelector(selector);
+    if (found) return found;
+    for (const element of root.querySelectorAll('*')) {
+      if (element.shadowRoot) {
+        const found = pierce(element.shadowRoot, selector);
+        if (found) return found;
+      }
+    }
+    return null;
+  };
+  const byText = (root, text) => {
+    let found = null;
+    for (const element of root.querySelectorAll('*')) {
+      if (element.textContent.includes(text)) found = element;
+    }
+    return found;
+  };
+  const query = (root, selector) => {
+    if (selector.startsWith('xpath/')) {
+      const doc = root.ownerDocument || root;
+      return doc.evaluate(selector.slice(6), root, null, XPathResult.FIRST_ORDERED_NODE_TYPE, null).singleNodeValue;
+    }
+    if (selector.startsWith('pierce/')) return pierce(root, selector.slice(7));
+    if (selector.startsWith('text/')) return byText(root, selector.slice(5));
+    if (selector.startsWith('aria/')) return null;
+    return root.querySelector(selector);
+  };
+  const resolve = (chain) => {
+    let element = null;
+    for (const selector of chain) {
+      const root = element ? (element.shadowRoot || element) : document;
+      element = query(root, selector);
+      if (!element) return null;
+    }
+    return element;
+  };
+
+  const element = selectors.map(resolve).find((element) => element);
+  if (!element) return null;
+  element.scrollIntoView({ block: 'center', inline: 'center' });
+  if (action === 'scroll') {
+    element.scrollTo(value.x, value.y);
+    return { x: 0, y: 0, done: true };
+  }
+  if (action === 'change') {
+    if (element.tagName === 'SELECT') {
+      element.value = value;
+      element.dispatchEvent(new Event('input', { bubbles: true }));
+      element.dispatchEvent(new Event('change', { bubbles: true }));
+      return { x: 0, y: 0, done: true };
+    }
+    element.focus();
+    if (typeof element.select === 'function') element.select();
+  }
+  const rect = element.getBoundingClientRect();
+  return { x: rect.x + rect.width / 2, y: rect.y + rect.height / 2, done: false };
+}
+"#;
+
+/// A sequence of steps replayed in the page before it is observed, e.g., to log
+/// in, click through a wizard or open a modal.
+///
+/// Scripts are either exports of the Chrome DevTools Recorder (JSON) or simple
+/// lists of steps (YAML or JSON) with the same step types and fields. The first
+/// `navigate` step loads the visited URL instead of the recorded one, so a script
+/// recorded on one page can be replayed on every URL. Without a `navigate` step,
+/// the visited URL is loaded before the first step.
+#[derive(Deserialize, Clone, Debug)]
+pub struct InteractionScript {
+    pub steps: Vec<Step>,
+}
+
+/// A step of an interaction script, named after the steps of the DevTools Recorder
+#[derive(Deserialize, Clone, Debug)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Step {
+    SetViewport {
+        width: u32,
+        height: u32,
+        #[serde(default = "default_device_scale_factor")]
+        device_scale_factor: f64,
+        #[serde(default)]
+        is_mobile: bool,
+    },
+    Navigate {
+        url: String,
+    },
+    Click {
+        #[serde(alias = "selector")]
+        selectors: Selectors,
+        #[serde(default)]
+        asserted_events: Vec<AssertedEvent>,
+        timeout: Option<u64>,
+    },
+    DoubleClick {
+        #[serde(alias = "selector")]
+        selectors: Selectors,
+        #[serde(default)]
+        asserted_events: Vec<AssertedEvent>,
+        timeout: Option<u64>,
+    },
+    Hover {
+        #[serde(alias = "selector")]
+        selectors: Selectors,
+        timeout: Option<u64>,
+    },
+    /// Replace the value of an input
+    Change {
+        #[serde(alias = "selector")]
+        selectors: Selectors,
+        value: String,
+        #[serde(default)]
+        asserted_events: Vec<AssertedEvent>,
+        timeout: Option<u64>,
+    },
+    KeyDown {
+        key: String,
+        #[serde(default)]
+        asserted_events: Vec<AssertedEvent>,
+    },
+    KeyUp {
+        key: String,
+        #[serde(default)]
+        asserted_events: Vec<AssertedEvent>,
+    },
+    /// Scroll the window, or the given element, to the given position
+    Scroll {
+        #[serde(default, alias = "selector")]
+        selectors: Option<Selectors>,
+        #[serde(default)]
+        x: f64,
+        #[serde(default)]
+        y: f64,
+    },
+    WaitForElement {
+        #[serde(alias = "selector")]
+        selectors: Selectors,
+        timeout: Option<u64>,
+    },
+    WaitForExpression {
+        expression: String,
+        timeout: Option<u64>,
+    },
+    /// Pause for the given number of milliseconds
+    Wait {
+        duration: u64,
+    },
+    /// Steps of the DevTools Recorder that have no effect on what is observed,
+    /// accepted so that recordings containing them can be replayed
+    EmulateNetworkConditions,
+    Close,
+    #[serde(rename = "customStep")]
+    Custom,
+}
+
+fn default_device_scale_factor() -> f64 {
+    1.0
+}
+
+/// Alternative selectors for an element, each one being a single selector or a
+/// chain of selectors going through shadow roots
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Selectors {
+    Single(String),
+    Many(Vec<SelectorChain>),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum SelectorChain {
+    Single(String),
+    Chain(Vec<String>),
+}
+
+impl Selectors {
+    fn chains(&self) -> Vec<Vec<&str>> {
+        match self {
+            Self::Single(selector) => vec![vec![selector]],
+            Self::Many(chains) => chains
+                .iter()
+                .map(|chain| match chain {
+                    SelectorChain::Single(selector) => vec![selector.as_str()],
+                    SelectorChain::Chain(chain) => chain.iter().map(String::as_str).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Event expected to happen after a step, only navigations are waited for
+#[derive(Deserialize, Clone, Debug)]
+pub struct AssertedEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+}
+
+/// Element found by [`FIND_ELEMENT_FUNCTION`]
+#[derive(Deserialize)]
+pub struct FoundElement {
+    pub x: f64,
+    pub y: f64,
+    /// The action has been performed entirely in the page
+    pub done: bool,
+}
+
+impl InteractionScript {
+    /// Load a script, in YAML if the file has a `.yaml` or `.yml` extension and
+    /// in JSON otherwise
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read interaction script at {:?}", path))?;
+
+        let is_yaml = path
+            .extension()
+            .is_some_and(|extension| extension == "yaml" || extension == "yml");
+        Self::parse(&contents, is_yaml)
+            .with_context(|| format!("failed to parse interaction script at {:?}", path))
+    }
+
+    /// Parse a script, either a mapping with its `steps` or a plain list of steps
+    fn parse(contents: &str, is_yaml: bool) -> Result<Self> {
+        if is_yaml {
+            let value: serde_yaml_ng::Value = serde_yaml_ng::from_str(contents)?;
+            Ok(match value {
+                serde_yaml_ng::Value::Sequence(_) => Self {
+                    steps: serde_yaml_ng::from_value(value)?,
+                },
+                value => serde_yaml_ng::from_value(value)?,
+            })
+        } else {
+            let value: Value = serde_json::from_str(contents)?;
+            Ok(match value {
+                Value::Array(_) => Self {
+                    steps: serde_json::from_value(value)?,
+                },
+                value => serde_json::from_value(value)?,
+            })
+        }
+    }
+
+    /// Split the steps into those to replay before loading the visited URL and
+    /// those to replay after, dropping the `navigate` step replaced by the URL
+    pub fn split_at_navigation(&self) -> (&[Step], &[Step]) {
+        match self
+            .steps
+            .iter()
+            .position(|step| matches!(step, Step::Navigate { .. }))
+        {
+            Some(i) => (&self.steps[..i], &self.steps[i + 1..]),
+            None => (&[], &self.steps),
+        }
+    }
+}
+
+/// Replay the given steps in order, failing on the first step that fails
+pub async fn replay_steps(page: &Page, steps: &[Step]) -> Result<()> {
+    for (i, step) in steps.iter().enumerate() {
+        replay_step(page, step)
+            .await
+            .with_context(|| format!("failed to replay step {}: {:?}", i + 1, step))?;
+    }
+    Ok(())
+}
+
+async fn replay_step(page: &Page, step: &Step) -> Result<()> {
+    match step {
+        Step::SetViewport {
+            width,
+            height,
+            device_scale_factor,
+            is_mobile,
+        } => {
+            page.execute(SetDeviceMetricsOverrideParams::new(
+                *width as i64,
+                *height as i64,
+                *device_scale_factor,
+                *is_mobile,
+            ))
+            .await?;
+        }
+        Step::Navigate { url } => {
+            page.goto(url.as_str()).await?;
+            page.wait_for_navigation().await?;
+        }
+        Step::Click {
+            selectors,
+            asserted_events,
+            timeout,
+        } => {
+            let element =
+                wait_for_element(page, selectors, "click", &Value::Null, *timeout).await?;
+            click(page, &element, 1).await?;
+            wait_for_asserted_navigation(page, asserted_events).await?;
+        }
+        Step::DoubleClick {
+            selectors,
+            asserted_events,
+            timeout,
+        } => {
+            let element =
+                wait_for_element(page, selectors, "click", &Value::Null, *timeout).await?;
+            click(page, &element, 1).await?;
+            click(page, &element, 2).await?;
+            wait_for_asserted_navigation(page, asserted_events).await?;
+        }
+        Step::Hover { selectors, timeout } => {
+            let element =
+                wait_for_element(page, selectors, "hover", &Value::Null, *timeout).await?;
+            page.move_mouse(Point::new(element.x, element.y)).await?;
+        }
+        Step::Change {
+            selectors,
+            value,
+            asserted_events,
+            timeout,
+        } => {
+            let element =
+                wait_for_element(page, selectors, "change", &json!(value), *timeout).await?;
+            if !element.done {
+                page.execute(InsertTextParams::new(value)).await?;
+            }
+            wait_for_asserted_navigation(page, asserted_events).await?;
+        }
+        Step::KeyDown {
+            key,
+            asserted_events,
+        } => {
+            dispatch_key(page, key, true).await?;
+            wait_for_asserted_navigation(page, asserted_events).await?;
+        }
+        Step::KeyUp {
+            key,
+            asserted_events,
+        } => {
+            dispatch_key(page, key, false).await?;
+            wait_for_asserted_navigation(page, asserted_events).await?;
+        }
+        Step::Scroll { selectors, x, y } => match selectors {
+            Some(selectors) => {
+                let position = json!({ "x": x, "y": y });
+                wait_for_element(page, selectors, "scroll", &position, None).await?;
+            }
+            None => {
+                page.evaluate_function(format!("() => window.scrollTo({}, {})", x, y))
+                    .await?;
+            }
+        },
+        Step::WaitForElement { selectors, timeout } => {
+            wait_for_element(page, selectors, "wait", &Value::Null, *timeout).await?;
+        }
+        Step::WaitForExpression {
+            expression,
+            timeout,
+        } => {
+            let function = format!("async () => Boolean(await ({}))", expression);
+            let wait = async {
+                loop {
+                    let value: bool = page
+                        .evaluate_function(function.as_str())
+                        .await?
+                        .into_value()?;
+                    if value {
+                        return Ok::<_, anyhow::Error>(());
+                    }
+                    async_std::task::sleep(STEP_POLL_INTERVAL).await;
+                }
+            };
+            async_std::future::timeout(step_timeout(*timeout), wait)
+                .await
+                .map_err(|_| anyhow!("expression did not become true before the timeout"))??;
+        }
+        Step::Wait { duration } => {
+            async_std::task::sleep(Duration::from_millis(*duration)).await;
+        }
+        Step::EmulateNetworkConditions | Step::Close | Step::Custom => {}
+    }
+    Ok(())
+}
+
+fn step_timeout(timeout: Option<u64>) -> Duration {
+    timeout.map_or(DEFAULT_STEP_TIMEOUT, Duration::from_millis)
+}
+
+/// Wait until an element matching the selectors is found, running the given
+/// action of [`FIND_ELEMENT_FUNCTION`] on it
+async fn wait_for_element(
+    page: &Page,
+    selectors: &Selectors,
+    action: &str,
+    value: &Value,
+    timeout: Option<u64>,
+) -> Result<FoundElement> {
+    let wait = async {
+        loop {
+            if let Some(element) = find_element(page, selectors, action, value).await? {
+                return Ok::<_, anyhow::Error>(element);
+            }
+            async_std::task::sleep(STEP_POLL_INTERVAL).await;
+        }
+    };
+    async_std::future::timeout(step_timeout(timeout), wait)
+        .await
+        .map_err(|_| anyhow!("no element matched the selectors before the timeout"))?
+}
+
+/// Look for an element matching the selectors once, running the given action of
+/// [`FIND_ELEMENT_FUNCTION`] on it
+pub async fn find_element(
+    page: &Page,
+    selectors: &Selectors,
+    action: &str,
+    value: &Value,
+) -> Result<Option<FoundElement>> {
+    let function = format!(
+        "() => ({})({}, {}, {})",
+        FIND_ELEMENT_FUNCTION,
+        serde_json::to_string(&selectors.chains())?,
+        serde_json::to_string(action)?,
+        serde_json::to_string(value)?
+    );
+    Ok(page.evaluate_function(function).await?.into_value()?)
+}
+
+/// Move the mouse to the element and click it
+pub async fn click(page: &Page, element: &FoundElement, click_count: i64) -> Result<()> {
+    page.move_mouse(Point::new(element.x, element.y)).await?;
+    for event_type in [
+        DispatchMouseEventType::MousePressed,
+        DispatchMouseEventType::MouseReleased,
+    ] {
+        let event = DispatchMouseEventParams::builder()
+            .r#type(event_type)
+            .x(element.x)
+            .y(element.y)
+            .button(MouseButton::Left)
+            .click_count(click_count)
+            .build()
+            .map_err(|e| anyhow!(e))?;
+        page.execute(event).await?;
+    }
+    Ok(())
+}
+
+async fn dispatch_key(page: &Page, key: &str, down: bool) -> Result<()> {
+    let definition =
+        keys::get_key_definition(key).ok_or_else(|| anyhow!("unknown key {:?}", key))?;
+    let text = definition
+        .text
+        .or((definition.key.len() == 1).then_some(definition.key));
+    let event_type = match (down, text) {
+        (false, _) => DispatchKeyEventType::KeyUp,
+        (true, Some(_)) => DispatchKeyEventType::KeyDown,
+        (true, None) => DispatchKeyEventType::RawKeyDown,
+    };
+
+    let mut event = DispatchKeyEventParams::builder()
+        .r#type(event_type)
+        .key(definition.key)
+        .code(definition.code)
+        .windows_virtual_key_code(definition.key_code)
+        .native_virtual_key_code(definition.key_code);
+    if let (true, Some(text)) = (down, text) {
+        event = event.text(text);
+    }
+    page.execute(event.build().map_err(|e| anyhow!(e))?).await?;
+    Ok(())
+}
+
+async fn wait_for_asserted_navigation(page: &Page, events: &[AssertedEvent]) -> Result<()> {
+    if events.iter().any(|event| event.event_type == "navigation") {
+        page.wait_for_navigation().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorder_export() {
+        let script = InteractionScript::parse(
+            r##"{
+                "title": "login",
+                "steps": [
+                    {"type": "setViewport", "width": 1280, "height": 720, "deviceScaleFactor": 2,
+                     "isMobile": false, "hasTouch": false, "isLandscape": false},
+                    {"type": "navigate", "url": "https://example.com/login",
+                     "assertedEvents": [{"type": "navigation", "url": "https://example.com/login", "title": ""}]},
+                    {"type": "click", "target": "main",
+                     "selectors": [["aria/Log in"], ["#login"], ["my-app", "button.login"]],
+                     "offsetX": 10, "offsetY": 5,
+                     "assertedEvents": [{"type": "navigation"}]},
+                    {"type": "emulateNetworkConditions", "download": 1000, "upload": 1000, "latency": 0},
+                    {"type": "customStep", "name": "foo", "parameters": {}},
+                    {"type": "close"}
+                ]
+            }"##,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(script.steps.len(), 6);
+        assert!(matches!(
+            script.steps[0],
+            Step::SetViewport { width: 1280, height: 720, device_scale_factor, is_mobile: false }
+                if device_scale_factor == 2.0
+        ));
+        let Step::Click {
+            selectors,
+            asserted_events,
+            timeout: None,
+        } = &script.steps[2]
+        else {
+            panic!("expected a click: {:?}", script.steps[2]);
+        };
+        assert_eq!(
+            selectors.chains(),
+            [
+                vec!["aria/Log in"],
+                vec!["#login"],
+                vec!["my-app", "button.login"]
+            ]
+        );
+        assert_eq!(asserted_events[0].event_type, "navigation");
+        assert!(matches!(script.steps[3], Step::EmulateNetworkConditions));
+        assert!(matches!(script.steps[4], Step::Custom));
+        assert!(matches!(script.steps[5], Step::Close));
+    }
+
+    #[test]
+    fn json_list_of_steps() {
+        let script = InteractionScript::parse(
+            r#"[{"type": "wait", "duration": 500}, {"type": "scroll", "y": 800}]"#,
+            false,
+        )
+        .unwrap();
+        assert!(matches!(script.steps[0], Step::Wait { duration: 500 }));
+        assert!(matches!(
+            script.steps[1],
+            Step::Scroll { selectors: None, x, y } if x == 0.0 && y == 800.0
+        ));
+    }
+
+    #[test]
+    fn yaml_list_of_steps() {
+        let script = InteractionScript::parse(
+            r##"
+# log in before observing the page
+- type: navigate
+  url: https://example.com/
+- type: change # replaces the current value
+  selectors:
+    - input[name=user]
+    - - my-form
+      - "#user"
+  value: 'admin'
+- type: keyDown
+  key: Enter
+  assertedEvents:
+    - type: navigation
+- {type: waitForExpression, expression: "document.readyState === 'complete'", timeout: 1000}
+"##,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(script.steps.len(), 4);
+        let Step::Change {
+            selectors, value, ..
+        } = &script.steps[1]
+        else {
+            panic!("expected a change: {:?}", script.steps[1]);
+        };
+        assert_eq!(value, "admin");
+        assert_eq!(
+            selectors.chains(),
+            [vec!["input[name=user]"], vec!["my-form", "#user"]]
+        );
+        assert!(matches!(
+            &script.steps[2],
+            Step::KeyDown { key, asserted_events } if key == "Enter" && asserted_events.len() == 1
+        ));
+        assert!(matches!(
+            script.steps[3],
+            Step::WaitForExpression {
+                timeout: Some(1000),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn yaml_mapping_of_steps() {
+        let script = InteractionScript::parse(
+            "title: open the menu\nsteps:\n  - type: hover\n    selector: nav .menu\n",
+            true,
+        )
+        .unwrap();
+        let Step::Hover { selectors, timeout } = &script.steps[0] else {
+            panic!("expected a hover: {:?}", script.steps[0]);
+        };
+        assert_eq!(selectors.chains(), [vec!["nav .menu"]]);
+        assert_eq!(*timeout, None);
+    }
+
+    #[test]
+    fn unknown_steps_are_rejected() {
+        let error = InteractionScript::parse("- type: clik\n  selector: a\n", true).unwrap_err();
+        assert!(error.to_string().contains("clik"), "{error}");
+        let error = InteractionScript::parse(r#"[{"type": "click"}]"#, false).unwrap_err();
+        assert!(error.to_string().contains("selectors"), "{error}");
+    }
+
+    #[test]
+    fn split_at_navigation() {
+        let script = InteractionScript::parse(
+            "- type: setViewport\n  width: 800\n  height: 600\n\
+             - type: navigate\n  url: https://example.com/\n\
+             - type: click\n  selector: a\n\
+             - type: navigate\n  url: https://example.com/next\n",
+            true,
+        )
+        .unwrap();
+        let (before, after) = script.split_at_navigation();
+        assert!(matches!(before, [Step::SetViewport { .. }]));
+        assert!(matches!(
+            after,
+            [Step::Click { .. }, Step::Navigate { url }] if url == "https://example.com/next"
+        ));
+
+        let script = InteractionScript::parse("[{\"type\": \"close\"}]", false).unwrap();
+        let (before, after) = script.split_at_navigation();
+        assert!(before.is_empty());
+        assert_eq!(after.len(), 1);
+    }
+}
@@ -0,0 +1,11 @@
+//! Parser of the logs written by the ghunter4chrome renderer, shared by the gadget
+//! finder with its benchmark and fuzz target.
+//!
+//! The `testing` feature adds a generator of synthetic logs, for benchmarks and fuzzing.
+
+pub mod constants;
+#[cfg(any(test, feature = "testing"))]
+pub mod log_generator;
+pub mod log_parser;
+pub mod log_writer;
+pub mod stack_trace;
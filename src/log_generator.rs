@@ -0,0 +1,269 @@
+use fastrand::Rng;
+
+use crate::{
+    constants::POLLUTED_MARKER,
+    log_parser::{LogMessage, PropertySource, ProxyAccessKind},
+    log_writer::LogWriter,
+    stack_trace::StackTrace,
+};
+
+const HOSTS: &[&str] = &["example.com", "cdn.example.com", "ads.example.net"];
+
+const PROPERTIES: &[&str] = &[
+    "href",
+    "innerHTML",
+    "src",
+    "srcdoc",
+    "data",
+    "config",
+    "template",
+    "onload",
+    "nonce",
+    "transport_url",
+    "__esModule",
+    "polyfill",
+];
+
+const SINK_TYPES: &[&str] = &[
+    "Element.innerHTML",
+    "Element.outerHTML",
+    "Document.write",
+    "HTMLScriptElement.src",
+    "HTMLIFrameElement.srcdoc",
+    "Location.href",
+    "eval",
+    "Function",
+];
+
+const CLASS_NAMES: &[&str] = &[
+    "HTMLDivElement",
+    "HTMLScriptElement",
+    "HTMLAnchorElement",
+    "Object",
+    "Array",
+];
+
+const FUNCTIONS: &[&str] = &[
+    "init",
+    "render",
+    "Object.assign",
+    "Module.load",
+    "e.exports",
+    "HTMLDivElement.set innerHTML",
+    "<anonymous>",
+];
+
+/// Strings that exercise the sized strings of the log format: whitespace,
+/// brackets and non-ASCII characters
+const TRICKY_STRINGS: &[&str] = &[
+    "",
+    " ",
+    "a b",
+    "line\nbreak",
+    "\n[LoadIC::Load_NOT_FOUND] 3 abc ",
+    "]",
+    "12 ",
+    "caf\u{e9}",
+    "\u{6f22}\u{5b57}",
+    "\u{1f600}",
+];
+
+/// Generator of random but realistic logs, for tests, benchmarks and fuzzing.
+///
+/// Logs are made of documents from a few origins, with undefined properties,
+/// reads of polluted properties, and sinks reached by taint markers. The same
+/// seed always produces the same log.
+pub struct LogGenerator {
+    rng: Rng,
+    documents: usize,
+    accesses: usize,
+}
+
+impl LogGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::with_seed(seed),
+            documents: 0,
+            accesses: 0,
+        }
+    }
+
+    /// Generate the given number of messages
+    pub fn messages(&mut self, count: usize) -> Vec<LogMessage> {
+        (0..count).map(|_| self.message()).collect()
+    }
+
+    /// Generate a log in the ghunter log format of at least `size` bytes
+    pub fn log(&mut self, size: usize) -> Vec<u8> {
+        let mut writer = LogWriter::new(Vec::with_capacity(size + 4096));
+        while writer.get_ref().len() < size {
+            let message = self.message();
+            writer
+                .write(&message)
+                .expect("writing to a vector never fails");
+        }
+        writer.into_inner()
+    }
+
+    /// Generate a single message
+    pub fn message(&mut self) -> LogMessage {
+        match self.rng.u8(..100) {
+            0..=4 => {
+                self.documents += 1;
+                LogMessage::Location { url: self.url() }
+            }
+            5..=9 if self.rng.bool() => LogMessage::DocumentStart {
+                url: None,
+                is_top_frame: None,
+            },
+            5..=9 => {
+                self.documents += 1;
+                LogMessage::DocumentStart {
+                    url: Some(self.url()),
+                    is_top_frame: Some(self.documents == 1 || self.rng.u8(..4) == 0),
+                }
+            }
+            10..=49 => LogMessage::UndefinedProperty {
+                name: self.property(),
+                source: self.property_source(),
+                stack_trace: self.stack_trace(false),
+            },
+            50..=64 => {
+                let key = self.property();
+                let value = self.marker();
+                LogMessage::PrototypeGet {
+                    key,
+                    value,
+                    stack_trace: self.stack_trace(true),
+                }
+            }
+            65..=74 => LogMessage::SinkReached {
+                sink_type: self.pick(SINK_TYPES).to_owned(),
+                value: format!("<img src=x alt=\"{}\">", self.marker()),
+                stack_trace: self.stack_trace(false),
+            },
+            75..=82 => LogMessage::AssignTaintedKey {
+                class_name: self.pick(CLASS_NAMES).to_owned(),
+                key: self.marker(),
+                value: self.string(),
+                source: self.property_source(),
+                stack_trace: self.stack_trace(false),
+            },
+            83..=87 => LogMessage::Polluted {
+                key: self.property(),
+                value: self.string(),
+                stack_trace: self.stack_trace(false),
+            },
+            88..=95 => {
+                let kind = self.pick(&[
+                    ProxyAccessKind::Get,
+                    ProxyAccessKind::Call,
+                    ProxyAccessKind::Construct,
+                    ProxyAccessKind::Coerce,
+                    ProxyAccessKind::Has,
+                ]);
+                LogMessage::ProxyAccess {
+                    kind,
+                    path: format!("{}.{}", self.property(), self.property()),
+                    value: self.marker(),
+                    stack_trace: self.stack_trace(true),
+                }
+            }
+            96..=97 => LogMessage::InDocument {
+                id: format!("doc{}", self.rng.usize(..=self.documents)),
+            },
+            _ => {
+                let (name, subtype) = match self.rng.bool() {
+                    true => ("From_JS", Some("CUSTOM_EVENT".to_owned())),
+                    false => ("Runtime::DefineProperty", None),
+                };
+                LogMessage::Unknown {
+                    name: name.to_owned(),
+                    subtype,
+                    raw: format!(
+                        "{} {}\n  continued",
+                        self.rng.u32(..),
+                        self.pick(PROPERTIES)
+                    ),
+                }
+            }
+        }
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.rng.usize(..items.len())]
+    }
+
+    fn property_source(&mut self) -> PropertySource {
+        match self.rng.bool() {
+            true => PropertySource::InlineCache,
+            false => PropertySource::RuntimeObject,
+        }
+    }
+
+    fn property(&mut self) -> String {
+        match self.rng.u8(..10) {
+            0 => format!("prop{}", self.rng.u32(..1000)),
+            1 => self.pick(TRICKY_STRINGS).to_owned(),
+            _ => self.pick(PROPERTIES).to_owned(),
+        }
+    }
+
+    fn string(&mut self) -> String {
+        match self.rng.bool() {
+            true => self.pick(TRICKY_STRINGS).to_owned(),
+            false => self.marker(),
+        }
+    }
+
+    /// A taint marker, e.g., `0xEFFACED:0:3`
+    fn marker(&mut self) -> String {
+        self.accesses += 1;
+        format!("{}:{}:{}", POLLUTED_MARKER, self.rng.u8(..4), self.accesses)
+    }
+
+    fn url(&mut self) -> String {
+        let host = self.pick(HOSTS);
+        format!(
+            "https://{}/page/{}?q={}",
+            host,
+            self.documents,
+            self.rng.u16(..)
+        )
+    }
+
+    fn script_url(&mut self) -> String {
+        let host = self.pick(HOSTS);
+        format!("https://{}/js/bundle.{}.js", host, self.rng.u8(..20))
+    }
+
+    /// A stack trace in the V8 format, with the `Error` header and the frame of
+    /// the polluting script for traces logged from JavaScript
+    fn stack_trace(&mut self, from_js: bool) -> StackTrace {
+        let mut lines = Vec::new();
+        if from_js {
+            lines.push("Error".to_owned());
+            lines.push("    at Object.get (<anonymous>:23:15)".to_owned());
+        }
+        for _ in 0..self.rng.usize(1..6) {
+            let function = self.pick(FUNCTIONS);
+            let url = self.script_url();
+            let (line, column) = (self.rng.u32(1..5000), self.rng.u32(1..300));
+            let frame = match self.rng.u8(..10) {
+                0 => format!("    at {}:{}:{}", url, line, column),
+                1 => format!("    at async {} ({}:{}:{})", function, url, line, column),
+                2 => format!(
+                    "    at eval (eval at {} ({}:{}:{}), <anonymous>:1:{})",
+                    function, url, line, column, column
+                ),
+                3 => "    at Array.forEach (<anonymous>)".to_owned(),
+                4 => "    at Math.max (native)".to_owned(),
+                5 => format!("    at new Widget ({}:{}:{})", url, line, column),
+                _ => format!("    at {} ({}:{}:{})", function, url, line, column),
+            };
+            lines.push(frame);
+        }
+
+        StackTrace::new(lines.join("\n"))
+    }
+}
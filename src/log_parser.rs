@@ -1,60 +1,389 @@
+use memchr::{memchr, memmem};
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fmt::Display,
     fs::File,
-    io::{BufReader, Read},
-    path::Path,
+    io::{ErrorKind, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
 };
 
-#[derive(Debug)]
+use crate::stack_trace::StackTrace;
+
+/// Maximum size of the strings of a record (including the raw contents of unknown
+/// records). Larger sizes are considered corrupted rather than allocated for.
+pub const MAX_STRING_SIZE: usize = 64 * 1024 * 1024;
+
+/// Maximum size of the tokens delimited by whitespace, i.e., record names,
+/// string sizes, sink types and `From_JS` message types
+pub const MAX_TOKEN_SIZE: usize = 1024;
+
+/// Size of the blocks read from logs that are parsed as they are read
+const READ_BLOCK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SiteLog {
     pub messages: Vec<LogMessage>,
+    /// Records that could not be parsed and were skipped: every malformed record in
+    /// lenient mode, and only a truncated last record otherwise
+    pub diagnostics: Vec<LogDiagnostic>,
+    /// Log files the messages come from (one per renderer process), in order
+    pub log_files: Vec<LogFileRange>,
 }
 
-#[derive(Debug)]
-pub enum LogMessage {
+/// Messages of a [`SiteLog`] that come from a single log file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogFileRange {
+    pub path: PathBuf,
+    /// Indices of the messages in [`SiteLog::messages`]
+    pub messages: Range<usize>,
+}
+
+/// Records logged while a single document was loaded in a frame.
+///
+/// Records logged from JavaScript are preceded by the identifier of their document
+/// (see [`LogMessage::InDocument`]), so they are attributed to it exactly. Records
+/// logged by the renderer itself do not tell which document they come from, so
+/// they are attributed to the document that last logged from JavaScript, or else
+/// to the document that started last. That document is only inferred once several
+/// documents are in the log file, see [`DocumentLog::inferred`].
+#[derive(Debug, Clone)]
+pub struct DocumentLog<'a> {
+    /// Identifier of the document, for logs that have one
+    pub id: Option<&'a str>,
+    /// URL of the document, from its start record or else from a `Location` record
+    pub url: Option<&'a str>,
+    /// Whether the document is loaded in the top frame, if known
+    pub is_top_frame: Option<bool>,
+    /// False for records of documents whose start is not in the log, such as the
+    /// records logged before the first document started
+    pub started: bool,
+    /// Log file of the renderer process the document was loaded in
+    pub log_file: Option<&'a Path>,
+    /// Messages attributed to the document, in order, without the
+    /// [`LogMessage::InDocument`] records
+    pub messages: Vec<&'a LogMessage>,
+    /// For each message, whether it may come from another document of the log
+    /// file, as it does not identify its document and several documents are in
+    /// the log file when it is logged
+    pub inferred: Vec<bool>,
+}
+
+impl SiteLog {
+    /// Concatenate the logs of several renderer processes, keeping track of which
+    /// log file each message comes from
+    pub fn merge(logs: impl IntoIterator<Item = SiteLog>) -> SiteLog {
+        let mut merged = SiteLog {
+            messages: Vec::new(),
+            diagnostics: Vec::new(),
+            log_files: Vec::new(),
+        };
+        for log in logs {
+            let start = merged.messages.len();
+            merged
+                .log_files
+                .extend(log.log_files.into_iter().map(|log_file| LogFileRange {
+                    path: log_file.path,
+                    messages: log_file.messages.start + start..log_file.messages.end + start,
+                }));
+            merged.messages.extend(log.messages);
+            merged.diagnostics.extend(log.diagnostics);
+        }
+        merged
+    }
+
+    /// Split the log into one segment per document, each starting with its
+    /// [`LogMessage::DocumentStart`] record, in the order the documents started.
+    /// The records of each log file that belong to no started document, if any,
+    /// come first in segments that are not started.
+    pub fn documents(&self) -> Vec<DocumentLog<'_>> {
+        let mut documents = Vec::new();
+        if self.log_files.is_empty() {
+            split_documents(None, &self.messages, &mut documents);
+        }
+        for log_file in &self.log_files {
+            let messages = &self.messages[log_file.messages.clone()];
+            split_documents(Some(&log_file.path), messages, &mut documents);
+        }
+        documents
+    }
+}
+
+/// Split the messages of a single log file into documents, see [`SiteLog::documents`]
+fn split_documents<'a>(
+    log_file: Option<&'a Path>,
+    messages: &'a [LogMessage],
+    documents: &mut Vec<DocumentLog<'a>>,
+) {
+    let new_document = |id, url, is_top_frame, started| DocumentLog {
+        id,
+        url,
+        is_top_frame,
+        started,
+        log_file,
+        messages: Vec::new(),
+        inferred: Vec::new(),
+    };
+    let first = documents.len();
+    documents.push(new_document(None, None, None, false));
+    let mut current = first;
+    let mut ids = HashMap::new();
+    // identifier of the next record's document, when it has not been seen yet
+    let mut pending_id = None;
+    let mut last_location = None;
+    // whether the next record is known to belong to the current document
+    let mut identified = false;
+
+    for msg in messages {
+        match msg {
+            LogMessage::InDocument { id } => {
+                match ids.get(id.as_str()) {
+                    Some(&index) => {
+                        current = index;
+                        pending_id = None;
+                    }
+                    None => pending_id = Some(id.as_str()),
+                }
+                identified = true;
+                continue;
+            }
+            LogMessage::DocumentStart { url, is_top_frame } => {
+                let id = pending_id.take();
+                let url = url.as_deref().or(last_location.take());
+                documents.push(new_document(id, url, *is_top_frame, true));
+                current = documents.len() - 1;
+                if let Some(id) = id {
+                    ids.insert(id, current);
+                }
+                identified = true;
+            }
+            _ => {
+                // the document started before the log does, e.g., when the log is
+                // parsed from an offset
+                if let Some(id) = pending_id.take() {
+                    documents.push(new_document(Some(id), None, None, false));
+                    current = documents.len() - 1;
+                    ids.insert(id, current);
+                }
+                if let LogMessage::Location { url } = msg {
+                    let document = &mut documents[current];
+                    // logs without URLs in start records rely on location records,
+                    // which can come either right before or right after the start
+                    if document.started && document.url.is_none() && document.messages.len() == 1 {
+                        document.url = Some(url.as_str());
+                    } else {
+                        last_location = Some(url.as_str());
+                    }
+                }
+            }
+        }
+        // the first document of the log file gets every record until another
+        // document shows up
+        let inferred = !identified && documents.len() - first > 2;
+        identified = false;
+        documents[current].messages.push(msg);
+        documents[current].inferred.push(inferred);
+    }
+
+    if documents[first].messages.is_empty() {
+        documents.remove(first);
+    }
+}
+
+/// A record that could not be parsed and was skipped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogDiagnostic {
+    /// Log file the record is in, when known
+    pub log_file: Option<PathBuf>,
+    /// Byte offset of the start of the record in the log
+    pub offset: u64,
+    /// Name of the record, if it could be read
+    pub record: Option<String>,
+    pub message: String,
+}
+
+/// A single record of a ghunter log.
+///
+/// The strings of the record are owned by default, but can also be borrowed from
+/// the log itself (see [`LogReader::from_slice`]).
+///
+/// When serialized, the variant is stored in the `type` field, in snake case.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "snake_case",
+    bound(serialize = "S: Serialize + AsRef<str>")
+)]
+pub enum LogMessage<S = String> {
     // LoadIC::Load_NOT_FOUND
     // Runtime::GetObjectProperty_NOT_FOUND
     UndefinedProperty {
-        name: String,
+        name: S,
         source: PropertySource,
-        stack_trace: String,
+        stack_trace: StackTrace<S>,
     },
     // StoreIC::Store
     // Runtime::SetObjectProperty_TAINTED
     AssignTaintedKey {
-        class_name: String,
-        key: String,
-        value: String,
+        class_name: S,
+        key: S,
+        value: S,
         source: PropertySource,
-        stack_trace: String,
+        stack_trace: StackTrace<S>,
     },
     // LogFrameLocation
     Location {
-        url: String,
+        url: S,
     },
     // Runtime::SetObjectProperty_PROTOTYPE
     Polluted {
-        key: String,
-        value: String,
-        stack_trace: String,
+        key: S,
+        value: S,
+        stack_trace: StackTrace<S>,
     },
     // From_JS + JS Prototype Get
     PrototypeGet {
-        key: String,
-        value: String,
-        stack_trace: String,
+        key: S,
+        value: S,
+        stack_trace: StackTrace<S>,
     },
     // LogIfStringTainted
     SinkReached {
-        sink_type: String,
-        value: String,
-        stack_trace: String,
+        sink_type: S,
+        value: S,
+        stack_trace: StackTrace<S>,
     },
     // From_JS + DOCUMENT_START
-    DocumentStart,
+    // From_JS + DOCUMENT_LOAD (older logs, without URL and frame)
+    DocumentStart {
+        url: Option<S>,
+        is_top_frame: Option<bool>,
+    },
+    // From_JS + IN_DOCUMENT, logged right before every other From_JS record
+    InDocument {
+        id: S,
+    },
+    // From_JS + PROXY_GET/PROXY_CALL/PROXY_CONSTRUCT/PROXY_COERCE/PROXY_HAS
+    ProxyAccess {
+        kind: ProxyAccessKind,
+        path: S,
+        value: S,
+        stack_trace: StackTrace<S>,
+    },
+    // Any other record (or From_JS message type), kept as is.
+    // As the format of its body is not known, it spans until the next line
+    // starting with `[` (or the end of the log).
+    Unknown {
+        name: S,
+        subtype: Option<S>,
+        raw: S,
+    },
 }
 
-#[derive(Debug)]
+impl<S> LogMessage<S> {
+    /// Convert every string of this message with the given function
+    pub fn map_str<T>(self, mut f: impl FnMut(S) -> T) -> LogMessage<T> {
+        match self {
+            Self::UndefinedProperty {
+                name,
+                source,
+                stack_trace,
+            } => LogMessage::UndefinedProperty {
+                name: f(name),
+                source,
+                stack_trace: stack_trace.map_str(&mut f),
+            },
+            Self::AssignTaintedKey {
+                class_name,
+                key,
+                value,
+                source,
+                stack_trace,
+            } => LogMessage::AssignTaintedKey {
+                class_name: f(class_name),
+                key: f(key),
+                value: f(value),
+                source,
+                stack_trace: stack_trace.map_str(&mut f),
+            },
+            Self::Location { url } => LogMessage::Location { url: f(url) },
+            Self::Polluted {
+                key,
+                value,
+                stack_trace,
+            } => LogMessage::Polluted {
+                key: f(key),
+                value: f(value),
+                stack_trace: stack_trace.map_str(&mut f),
+            },
+            Self::PrototypeGet {
+                key,
+                value,
+                stack_trace,
+            } => LogMessage::PrototypeGet {
+                key: f(key),
+                value: f(value),
+                stack_trace: stack_trace.map_str(&mut f),
+            },
+            Self::SinkReached {
+                sink_type,
+                value,
+                stack_trace,
+            } => LogMessage::SinkReached {
+                sink_type: f(sink_type),
+                value: f(value),
+                stack_trace: stack_trace.map_str(&mut f),
+            },
+            Self::DocumentStart { url, is_top_frame } => LogMessage::DocumentStart {
+                url: url.map(&mut f),
+                is_top_frame,
+            },
+            Self::InDocument { id } => LogMessage::InDocument { id: f(id) },
+            Self::ProxyAccess {
+                kind,
+                path,
+                value,
+                stack_trace,
+            } => LogMessage::ProxyAccess {
+                kind,
+                path: f(path),
+                value: f(value),
+                stack_trace: stack_trace.map_str(&mut f),
+            },
+            Self::Unknown { name, subtype, raw } => LogMessage::Unknown {
+                name: f(name),
+                subtype: subtype.map(&mut f),
+                raw: f(raw),
+            },
+        }
+    }
+}
+
+impl LogMessage<&str> {
+    pub fn into_owned(self) -> LogMessage {
+        self.map_str(str::to_owned)
+    }
+}
+
+/// Operation performed on a proxy-backed taint value
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyAccessKind {
+    /// A property of the value was read, e.g., `polluted.x`
+    Get,
+    /// The value was called, e.g., `polluted()`
+    Call,
+    /// The value was used as a constructor, e.g., `new polluted()`
+    Construct,
+    /// The value was coerced into a primitive, e.g., `${polluted}`
+    Coerce,
+    /// The value was checked for a property, e.g., `"x" in polluted`
+    Has,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PropertySource {
     InlineCache,
     RuntimeObject,
@@ -63,8 +392,55 @@ pub enum PropertySource {
 #[derive(Debug)]
 pub enum LogError {
     Io(std::io::Error),
-    Utf8(std::string::FromUtf8Error),
+    Utf8(std::str::Utf8Error),
     IntParse(std::num::ParseIntError),
+    /// A string or token is larger than the given limit
+    SizeLimit {
+        size: usize,
+        limit: usize,
+    },
+    /// An error that happened while parsing a record starting at the given byte offset
+    InRecord {
+        offset: u64,
+        record: Option<String>,
+        error: Box<LogError>,
+    },
+}
+
+impl LogError {
+    fn in_record(self, offset: u64, record: Option<&str>) -> Self {
+        Self::InRecord {
+            offset,
+            record: record.map(str::to_owned),
+            error: Box::new(self),
+        }
+    }
+
+    /// Move the offset of a record error by the given amount, for logs parsed
+    /// from the middle of a file
+    fn shifted(self, amount: u64) -> Self {
+        match self {
+            Self::InRecord {
+                offset,
+                record,
+                error,
+            } => Self::InRecord {
+                offset: offset + amount,
+                record,
+                error,
+            },
+            error => error,
+        }
+    }
+
+    /// Whether parsing can continue at the next record after this error
+    fn is_recoverable(&self) -> bool {
+        match self {
+            Self::Io(error) => error.kind() == ErrorKind::UnexpectedEof,
+            Self::Utf8(_) | Self::IntParse(_) | Self::SizeLimit { .. } => true,
+            Self::InRecord { error, .. } => error.is_recoverable(),
+        }
+    }
 }
 
 impl From<std::io::Error> for LogError {
@@ -73,9 +449,15 @@ impl From<std::io::Error> for LogError {
     }
 }
 
+impl From<std::str::Utf8Error> for LogError {
+    fn from(value: std::str::Utf8Error) -> Self {
+        Self::Utf8(value)
+    }
+}
+
 impl From<std::string::FromUtf8Error> for LogError {
     fn from(value: std::string::FromUtf8Error) -> Self {
-        Self::Utf8(value)
+        Self::Utf8(value.utf8_error())
     }
 }
 
@@ -87,148 +469,967 @@ impl From<std::num::ParseIntError> for LogError {
 
 impl Display for LogError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Self::InRecord {
+                offset,
+                record: Some(record),
+                error,
+            } => write!(
+                f,
+                "failed to parse [{}] record at byte {}: {}",
+                record, offset, error
+            ),
+            Self::InRecord {
+                offset,
+                record: None,
+                error,
+            } => write!(f, "failed to parse record at byte {}: {}", offset, error),
+            Self::SizeLimit { size, limit } => {
+                write!(f, "size {} exceeds the limit of {} bytes", size, limit)
+            }
+            Self::Io(error) if error.kind() == ErrorKind::UnexpectedEof => {
+                write!(f, "unexpected end of log")
+            }
+            Self::Io(error) => write!(f, "failed to read log: {}", error),
+            Self::Utf8(error) => write!(f, "invalid UTF-8 string: {}", error),
+            Self::IntParse(error) => write!(f, "invalid string size: {}", error),
+        }
     }
 }
 
-impl std::error::Error for LogError {}
+impl std::error::Error for LogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Utf8(error) => Some(error),
+            Self::IntParse(error) => Some(error),
+            Self::SizeLimit { .. } => None,
+            Self::InRecord { error, .. } => Some(error),
+        }
+    }
+}
 
+/// Parse the log at the given path, failing on the first malformed record.
+///
+/// A last record that is cut short by the end of the log (e.g., because the renderer
+/// was killed while writing it) is not an error: it is skipped and reported as a
+/// diagnostic, like in lenient mode.
 pub fn parse_log(path: &Path) -> Result<SiteLog, LogError> {
-    let file = File::open(path)?;
-    let buf_reader = BufReader::new(file);
+    read_log(path, 0, false)
+}
+
+/// Parse the log at the given path, skipping malformed records (e.g., truncated
+/// because the renderer was killed while writing them) instead of failing
+pub fn parse_log_lenient(path: &Path) -> Result<SiteLog, LogError> {
+    read_log(path, 0, true)
+}
+
+/// Parse the part of the log at the given path that starts at byte `offset`,
+/// e.g., the records appended to it since it was last read
+pub fn parse_log_since(path: &Path, offset: u64, lenient: bool) -> Result<SiteLog, LogError> {
+    read_log(path, offset, lenient)
+}
+
+fn read_log(path: &Path, offset: u64, lenient: bool) -> Result<SiteLog, LogError> {
+    // the log is parsed as it is read, so that only the parsed messages are kept
+    // in memory rather than the whole log as well
+    let mut file = File::open(path)?;
+    let offset = offset.min(file.metadata()?.len());
+    file.seek(SeekFrom::Start(offset))?;
 
-    let mut messages: Vec<LogMessage> = Vec::new();
+    let mut reader = LogReader::new(file).lenient(lenient);
+    let messages = reader
+        .by_ref()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| error.shifted(offset))?;
+
+    let diagnostics = reader
+        .into_diagnostics()
+        .into_iter()
+        .map(|diagnostic| LogDiagnostic {
+            log_file: Some(path.to_path_buf()),
+            offset: diagnostic.offset + offset,
+            ..diagnostic
+        })
+        .collect();
+
+    Ok(SiteLog {
+        log_files: vec![LogFileRange {
+            path: path.to_path_buf(),
+            messages: 0..messages.len(),
+        }],
+        messages,
+        diagnostics,
+    })
+}
+
+/// Byte-level access to a ghunter log, abstracting over where the log is read from
+pub trait LogSource {
+    type Str: AsRef<str> + Clone;
+
+    /// Skip past the next `[`, which starts a record.
+    /// Returns false if the end of the log has been reached.
+    fn skip_to_record(&mut self) -> Result<bool, LogError>;
+
+    /// Read until the next whitespace, which is consumed but not returned
+    fn read_until_whitespace(&mut self) -> Result<Self::Str, LogError>;
+
+    /// Read the size of a string, up to the next whitespace
+    fn read_size(&mut self) -> Result<usize, LogError> {
+        Ok(self.read_until_whitespace()?.as_ref().parse()?)
+    }
+
+    /// Read the name of a record, right after its `[`, without the closing `]`
+    fn read_record_name(&mut self) -> Result<Self::Str, LogError>;
+
+    /// Read exactly `size` bytes, consuming the separator after them
+    fn read_exact_string(&mut self, size: usize) -> Result<Self::Str, LogError>;
+
+    /// Read the rest of a record of unknown format, i.e., until the next line
+    /// starting with `[` (exclusive) or the end of the log.
+    /// The trailing newline is consumed but not returned.
+    fn read_raw_record(&mut self) -> Result<Self::Str, LogError>;
+
+    /// Number of bytes consumed so far
+    fn offset(&self) -> u64;
+
+    /// Whether the end of the log has been reached
+    fn at_end(&mut self) -> Result<bool, LogError>;
+
+    /// Prepare to look for the next record after a malformed record starting at
+    /// `record_start`, i.e., go to the first line after its `[` that starts with
+    /// `[`. Brackets within the line of the malformed record may be in its strings,
+    /// so they do not start records.
+    fn recover(&mut self, record_start: u64) -> Result<(), LogError>;
+}
+
+/// Log source reading incrementally from any reader (such as a file or a pipe),
+/// in blocks.
+///
+/// The bytes of the current record are kept until the next record starts, so that
+/// lenient mode can go back to the start of a malformed record without seeking.
+pub struct ReadSource<R: Read> {
+    reader: R,
+    /// Bytes read and not discarded yet, starting at or before the current record,
+    /// followed by the space to read the next block into
+    buf: Vec<u8>,
+    /// End of the bytes read in `buf`
+    end: usize,
+    /// Position in `buf` of the next byte to parse
+    pos: usize,
+    /// Position in `buf` of the start of the current record
+    record_start: usize,
+    /// Offset in the log of the first byte of `buf`
+    buf_offset: u64,
+    eof: bool,
+}
+
+impl<R: Read> ReadSource<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: Vec::new(),
+            end: 0,
+            pos: 0,
+            record_start: 0,
+            buf_offset: 0,
+            eof: false,
+        }
+    }
+
+    /// Read the next block, discarding the bytes before the current record.
+    /// Returns false if the end of the log has been reached.
+    fn read_block(&mut self) -> Result<bool, LogError> {
+        if self.eof {
+            return Ok(false);
+        }
+        if self.record_start > 0 {
+            self.buf.copy_within(self.record_start..self.end, 0);
+            self.end -= self.record_start;
+            self.pos -= self.record_start;
+            self.buf_offset += self.record_start as u64;
+            self.record_start = 0;
+        }
+
+        // the buffer only grows for records larger than a block
+        if self.end < self.end + READ_BLOCK_SIZE {
+            self.buf.resize(self.end + READ_BLOCK_SIZE, 0);
+        }
+        let read = loop {
+            match self.reader.read(&mut self.buf[self.end..]) {
+                Ok(read) => break read,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error.into()),
+            }
+        };
+        self.end += read;
+        self.eof = read == 0;
+        Ok(!self.eof)
+    }
 
-    let mut iter = buf_reader.bytes();
+    /// Read until at least `size` bytes are available after the current position,
+    /// or the end of the log
+    fn fill(&mut self, size: usize) -> Result<&[u8], LogError> {
+        while self.end - self.pos < size && self.read_block()? {}
+        Ok(&self.buf[self.pos..self.end])
+    }
 
-    'outer: loop {
+    /// Range in `buf` of the bytes until the next whitespace, which is consumed.
+    /// No more than the maximum size of a token is consumed.
+    fn read_token(&mut self) -> Result<Range<usize>, LogError> {
+        let mut searched = 0;
         loop {
-            match iter.next() {
-                Some(b) => {
-                    if b? == b'[' {
-                        break;
-                    }
-                }
-                None => break 'outer,
+            let rest = &self.buf[self.pos..self.end];
+            let limit = rest.len().min(MAX_TOKEN_SIZE + 1);
+            if let Some(i) = rest[searched..limit]
+                .iter()
+                .position(u8::is_ascii_whitespace)
+            {
+                let token = self.pos..self.pos + searched + i;
+                self.pos = token.end + 1;
+                return Ok(token);
+            }
+            searched = limit;
+            if limit > MAX_TOKEN_SIZE || !self.read_block()? {
+                check_size(limit, MAX_TOKEN_SIZE)?;
+                let token = self.pos..self.pos + limit;
+                self.pos = token.end;
+                return Ok(token);
             }
         }
-        let name = read_until_whitespace(&mut iter)?;
+    }
 
-        macro_rules! handle_undefined_property {
-            ($source: expr) => {
-                let property = read_sized_string(&mut iter)?;
-                let stack_trace = read_sized_string(&mut iter)?;
-                messages.push(LogMessage::UndefinedProperty {
-                    name: property,
-                    source: $source,
-                    stack_trace,
-                });
-            };
+    fn str(&self, range: Range<usize>) -> Result<&str, LogError> {
+        Ok(std::str::from_utf8(&self.buf[range])?)
+    }
+}
+
+impl<R: Read> LogSource for ReadSource<R> {
+    type Str = String;
+
+    fn skip_to_record(&mut self) -> Result<bool, LogError> {
+        loop {
+            if let Some(i) = memchr(b'[', &self.buf[self.pos..self.end]) {
+                self.record_start = self.pos + i;
+                self.pos = self.record_start + 1;
+                return Ok(true);
+            }
+            self.pos = self.end;
+            self.record_start = self.pos;
+            if !self.read_block()? {
+                return Ok(false);
+            }
         }
+    }
 
-        macro_rules! handle_assign_tainted_key {
-            ($source: expr) => {
-                let class_name = read_sized_string(&mut iter)?;
-                let key = read_sized_string(&mut iter)?;
-                let value = read_sized_string(&mut iter)?;
-                let stack_trace = read_sized_string(&mut iter)?;
-                messages.push(LogMessage::AssignTaintedKey {
-                    class_name,
-                    key,
-                    value,
-                    source: $source,
-                    stack_trace,
-                })
-            };
+    fn read_until_whitespace(&mut self) -> Result<String, LogError> {
+        let token = self.read_token()?;
+        Ok(self.str(token)?.to_owned())
+    }
+
+    fn read_size(&mut self) -> Result<usize, LogError> {
+        let token = self.read_token()?;
+        Ok(self.str(token)?.parse()?)
+    }
+
+    fn read_record_name(&mut self) -> Result<String, LogError> {
+        let token = self.read_token()?;
+        let name = self.str(token)?;
+        Ok(name.strip_suffix(']').unwrap_or(name).to_owned())
+    }
+
+    fn read_exact_string(&mut self, size: usize) -> Result<String, LogError> {
+        // along with the separator after the string
+        let available = self.fill(size + 1)?.len();
+        if available < size {
+            self.pos = self.end;
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
         }
 
-        match name.as_str() {
-            "LoadIC::Load_NOT_FOUND]" => {
-                handle_undefined_property!(PropertySource::InlineCache);
+        let string = self.str(self.pos..self.pos + size)?.to_owned();
+        self.pos += (size + 1).min(available);
+        Ok(string)
+    }
+
+    fn read_raw_record(&mut self) -> Result<String, LogError> {
+        let mut searched = 0;
+        let (len, consumed) = loop {
+            let rest = &self.buf[self.pos..self.end];
+            if let Some(i) = memmem::find(&rest[searched..], b"\n[") {
+                break (searched + i, searched + i + 1);
             }
-            "Runtime::GetObjectProperty_NOT_FOUND]" => {
-                handle_undefined_property!(PropertySource::RuntimeObject);
+            // a record without a next one could be arbitrarily large
+            if rest.len() > MAX_STRING_SIZE + 1 {
+                break (rest.len(), rest.len());
             }
-            "StoreIC::Store]" => {
-                handle_assign_tainted_key!(PropertySource::InlineCache);
+            // the newline of the next record may be the last byte read
+            searched = rest.len().saturating_sub(1);
+            if !self.read_block()? {
+                let rest = &self.buf[self.pos..self.end];
+                break (rest.strip_suffix(b"\n").unwrap_or(rest).len(), rest.len());
             }
-            "Runtime::SetObjectProperty_TAINTED]" => {
-                handle_assign_tainted_key!(PropertySource::RuntimeObject);
+        };
+        check_size(len, MAX_STRING_SIZE)?;
+
+        let raw = self.str(self.pos..self.pos + len)?.to_owned();
+        self.pos += consumed;
+        Ok(raw)
+    }
+
+    fn offset(&self) -> u64 {
+        self.buf_offset + self.pos as u64
+    }
+
+    fn at_end(&mut self) -> Result<bool, LogError> {
+        Ok(self.fill(1)?.is_empty())
+    }
+
+    fn recover(&mut self, record_start: u64) -> Result<(), LogError> {
+        // the current record is still in the buffer
+        let mut from = (record_start + 1 - self.buf_offset) as usize;
+        loop {
+            if let Some(i) = memmem::find(&self.buf[from..self.end], b"\n[") {
+                self.pos = from + i + 1;
+                return Ok(());
             }
-            "LogFrameLocation]" => {
-                let new_location = read_sized_string(&mut iter)?;
-                messages.push(LogMessage::Location { url: new_location });
+            // the newline of the next record may be the last byte read
+            self.pos = self.end.saturating_sub(1).max(from);
+            self.record_start = self.pos;
+            if !self.read_block()? {
+                self.pos = self.end;
+                return Ok(());
             }
-            "Runtime::SetObjectProperty_PROTOTYPE]" => {
-                let key = read_sized_string(&mut iter)?;
-                let value = read_sized_string(&mut iter)?;
-                let stack_trace = read_sized_string(&mut iter)?;
-                messages.push(LogMessage::Polluted {
-                    key,
-                    value,
-                    stack_trace,
-                })
+            from = self.pos;
+        }
+    }
+}
+
+/// Log source reading from a log that is entirely in memory, without copying
+pub struct SliceSource<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> LogSource for SliceSource<'a> {
+    type Str = &'a str;
+
+    fn skip_to_record(&mut self) -> Result<bool, LogError> {
+        match memchr(b'[', &self.data[self.pos..]) {
+            Some(i) => {
+                self.pos += i + 1;
+                Ok(true)
+            }
+            None => {
+                self.pos = self.data.len();
+                Ok(false)
+            }
+        }
+    }
+
+    fn read_until_whitespace(&mut self) -> Result<&'a str, LogError> {
+        let rest = &self.data[self.pos..];
+        // no need to look further than the maximum size of a token
+        let searched = &rest[..rest.len().min(MAX_TOKEN_SIZE + 1)];
+        let (bytes, consumed) = match searched.iter().position(u8::is_ascii_whitespace) {
+            Some(i) => (&rest[..i], i + 1),
+            None => (searched, searched.len()),
+        };
+        check_size(bytes.len(), MAX_TOKEN_SIZE)?;
+        self.pos += consumed;
+
+        Ok(std::str::from_utf8(bytes)?)
+    }
+
+    fn read_record_name(&mut self) -> Result<&'a str, LogError> {
+        let name = self.read_until_whitespace()?;
+        Ok(name.strip_suffix(']').unwrap_or(name))
+    }
+
+    fn read_exact_string(&mut self, size: usize) -> Result<&'a str, LogError> {
+        let rest = &self.data[self.pos..];
+        if rest.len() < size {
+            self.pos = self.data.len();
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+
+        // take byte (space) after string
+        self.pos = (self.pos + size + 1).min(self.data.len());
+
+        Ok(std::str::from_utf8(&rest[..size])?)
+    }
+
+    fn read_raw_record(&mut self) -> Result<&'a str, LogError> {
+        let rest = &self.data[self.pos..];
+        let (bytes, consumed) = match memmem::find(rest, b"\n[") {
+            Some(i) => (&rest[..i], i + 1),
+            None => (rest.strip_suffix(b"\n").unwrap_or(rest), rest.len()),
+        };
+        check_size(bytes.len(), MAX_STRING_SIZE)?;
+        self.pos += consumed;
+
+        Ok(std::str::from_utf8(bytes)?)
+    }
+
+    fn offset(&self) -> u64 {
+        self.pos as u64
+    }
+
+    fn at_end(&mut self) -> Result<bool, LogError> {
+        Ok(self.pos >= self.data.len())
+    }
+
+    fn recover(&mut self, record_start: u64) -> Result<(), LogError> {
+        let from = (record_start as usize + 1).min(self.data.len());
+        self.pos = match memmem::find(&self.data[from..], b"\n[") {
+            Some(i) => from + i + 1,
+            None => self.data.len(),
+        };
+        Ok(())
+    }
+}
+
+/// Parser of ghunter logs, yielding one message at a time.
+///
+/// Records that are not understood are yielded as [`LogMessage::Unknown`].
+/// After an error is yielded, the iterator is exhausted, unless in lenient mode,
+/// where malformed records are skipped as well and recorded as diagnostics.
+/// In both modes, a last record cut short by the end of the log is skipped and
+/// recorded as a diagnostic.
+pub struct LogReader<S: LogSource> {
+    source: S,
+    done: bool,
+    lenient: bool,
+    diagnostics: Vec<LogDiagnostic>,
+}
+
+impl<R: Read> LogReader<ReadSource<R>> {
+    /// Parse a log incrementally from the given reader, keeping only the
+    /// current record in memory. Offsets are relative to the position of the
+    /// reader when parsing starts.
+    pub fn new(reader: R) -> Self {
+        Self {
+            source: ReadSource::new(reader),
+            done: false,
+            lenient: false,
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+impl<'a> LogReader<SliceSource<'a>> {
+    /// Parse a log that is entirely in memory, with the strings of each message
+    /// borrowed from it
+    pub fn from_slice(data: &'a [u8]) -> Self {
+        Self {
+            source: SliceSource { data, pos: 0 },
+            done: false,
+            lenient: false,
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+impl<S: LogSource> LogReader<S> {
+    /// Enable or disable lenient mode, where malformed records are skipped
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Records skipped so far in lenient mode
+    pub fn into_diagnostics(self) -> Vec<LogDiagnostic> {
+        self.diagnostics
+    }
+
+    /// Read records until one of them results in a message
+    fn read_message(&mut self) -> Result<Option<LogMessage<S::Str>>, LogError> {
+        loop {
+            if !self.source.skip_to_record()? {
+                return Ok(None);
+            }
+            let record_start = self.source.offset() - 1;
+
+            let result = match self.source.read_record_name() {
+                Ok(name) => parse_record(&mut self.source, name.clone())
+                    .map_err(|error| error.in_record(record_start, Some(name.as_ref()))),
+                Err(error) => Err(error.in_record(record_start, None)),
+            };
+
+            let error = match result {
+                Ok(message) => return Ok(Some(message)),
+                Err(error) => error,
+            };
+            // outside of lenient mode, only a record cut short by the end of the log
+            // is skipped, as it is the last one anyway
+            if !error.is_recoverable() || !(self.lenient || self.source.at_end()?) {
+                return Err(error);
+            }
+
+            let LogError::InRecord { record, error, .. } = error else {
+                unreachable!("record errors are always wrapped")
+            };
+            self.diagnostics.push(LogDiagnostic {
+                log_file: None,
+                offset: record_start,
+                record,
+                message: error.to_string(),
+            });
+            if !self.lenient {
+                return Ok(None);
             }
-            "From_JS]" => {
-                let msg_type = read_until_whitespace(&mut iter)?;
-                match msg_type.as_str() {
-                    "DOCUMENT_LOAD" => {
-                        messages.push(LogMessage::DocumentStart);
+            self.source.recover(record_start)?;
+        }
+    }
+}
+
+/// Parse the contents of the record with the given name
+fn parse_record<S: LogSource>(
+    source: &mut S,
+    name: S::Str,
+) -> Result<LogMessage<S::Str>, LogError> {
+    macro_rules! handle_undefined_property {
+        ($source: expr) => {{
+            let property = read_sized_string(source)?;
+            let stack_trace = read_stack_trace(source)?;
+            LogMessage::UndefinedProperty {
+                name: property,
+                source: $source,
+                stack_trace,
+            }
+        }};
+    }
+
+    macro_rules! handle_assign_tainted_key {
+        ($source: expr) => {{
+            let class_name = read_sized_string(source)?;
+            let key = read_sized_string(source)?;
+            let value = read_sized_string(source)?;
+            let stack_trace = read_stack_trace(source)?;
+            LogMessage::AssignTaintedKey {
+                class_name,
+                key,
+                value,
+                source: $source,
+                stack_trace,
+            }
+        }};
+    }
+
+    macro_rules! handle_proxy_access {
+        ($kind: expr) => {{
+            let path = read_sized_string(source)?;
+            let value = read_sized_string(source)?;
+            let stack_trace = read_stack_trace(source)?;
+            LogMessage::ProxyAccess {
+                kind: $kind,
+                path,
+                value,
+                stack_trace,
+            }
+        }};
+    }
+
+    let message = match name.as_ref() {
+        "LoadIC::Load_NOT_FOUND" => {
+            handle_undefined_property!(PropertySource::InlineCache)
+        }
+        "Runtime::GetObjectProperty_NOT_FOUND" => {
+            handle_undefined_property!(PropertySource::RuntimeObject)
+        }
+        "StoreIC::Store" => {
+            handle_assign_tainted_key!(PropertySource::InlineCache)
+        }
+        "Runtime::SetObjectProperty_TAINTED" => {
+            handle_assign_tainted_key!(PropertySource::RuntimeObject)
+        }
+        "LogFrameLocation" => {
+            let new_location = read_sized_string(source)?;
+            LogMessage::Location { url: new_location }
+        }
+        "Runtime::SetObjectProperty_PROTOTYPE" => {
+            let key = read_sized_string(source)?;
+            let value = read_sized_string(source)?;
+            let stack_trace = read_stack_trace(source)?;
+            LogMessage::Polluted {
+                key,
+                value,
+                stack_trace,
+            }
+        }
+        "From_JS" => {
+            let msg_type = source.read_until_whitespace()?;
+            match msg_type.as_ref() {
+                "DOCUMENT_START" => {
+                    let url = read_sized_string(source)?;
+                    let frame = read_sized_string(source)?;
+                    LogMessage::DocumentStart {
+                        url: Some(url),
+                        is_top_frame: Some(frame.as_ref() == "top"),
                     }
-                    "PROTOTYPE_GET" => {
-                        let key = read_sized_string(&mut iter)?;
-                        let value = read_sized_string(&mut iter)?;
-                        let stack_trace = read_sized_string(&mut iter)?;
-                        messages.push(LogMessage::PrototypeGet {
-                            key,
-                            value,
-                            stack_trace,
-                        })
+                }
+                "DOCUMENT_LOAD" => LogMessage::DocumentStart {
+                    url: None,
+                    is_top_frame: None,
+                },
+                "IN_DOCUMENT" => LogMessage::InDocument {
+                    id: read_sized_string(source)?,
+                },
+                "PROTOTYPE_GET" => {
+                    let key = read_sized_string(source)?;
+                    let value = read_sized_string(source)?;
+                    let stack_trace = read_stack_trace(source)?;
+                    LogMessage::PrototypeGet {
+                        key,
+                        value,
+                        stack_trace,
                     }
-                    _ => {}
                 }
-                // TODO
+                "PROXY_GET" => handle_proxy_access!(ProxyAccessKind::Get),
+                "PROXY_CALL" => handle_proxy_access!(ProxyAccessKind::Call),
+                "PROXY_CONSTRUCT" => handle_proxy_access!(ProxyAccessKind::Construct),
+                "PROXY_COERCE" => handle_proxy_access!(ProxyAccessKind::Coerce),
+                "PROXY_HAS" => handle_proxy_access!(ProxyAccessKind::Has),
+                _ => LogMessage::Unknown {
+                    name,
+                    subtype: Some(msg_type),
+                    raw: source.read_raw_record()?,
+                },
+            }
+        }
+        "LogIfStringTainted" => {
+            let sink_type = source.read_until_whitespace()?;
+            let value = read_sized_string(source)?;
+            let stack_trace = read_stack_trace(source)?;
+            LogMessage::SinkReached {
+                sink_type,
+                value,
+                stack_trace,
+            }
+        }
+        _ => LogMessage::Unknown {
+            name,
+            subtype: None,
+            raw: source.read_raw_record()?,
+        },
+    };
+
+    Ok(message)
+}
+
+impl<S: LogSource> Iterator for LogReader<S> {
+    type Item = Result<LogMessage<S::Str>, LogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_message() {
+            Ok(Some(message)) => Some(Ok(message)),
+            Ok(None) => {
+                self.done = true;
+                None
             }
-            "LogIfStringTainted]" => {
-                let sink_type = read_until_whitespace(&mut iter)?;
-                let value = read_sized_string(&mut iter)?;
-                let stack_trace = read_sized_string(&mut iter)?;
-                messages.push(LogMessage::SinkReached {
-                    sink_type,
-                    value,
-                    stack_trace,
-                })
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
             }
-            _ => {}
         }
     }
+}
 
-    Ok(SiteLog { messages })
+fn read_sized_string<S: LogSource>(source: &mut S) -> Result<S::Str, LogError> {
+    let size = source.read_size()?;
+    check_size(size, MAX_STRING_SIZE)?;
+    source.read_exact_string(size)
 }
 
-fn read_until_whitespace(
-    iter: &mut impl Iterator<Item = Result<u8, std::io::Error>>,
-) -> Result<String, LogError> {
-    let str_bytes: Vec<u8> = iter
-        .take_while(|c| {
-            c.as_ref()
-                .ok()
-                .map(|c| !c.is_ascii_whitespace())
-                .unwrap_or(false)
-        })
-        .collect::<Result<Vec<_>, _>>()?;
+fn check_size(size: usize, limit: usize) -> Result<(), LogError> {
+    if size > limit {
+        return Err(LogError::SizeLimit { size, limit });
+    }
+    Ok(())
+}
 
-    Ok(String::from_utf8(str_bytes)?)
+fn read_stack_trace<S: LogSource>(source: &mut S) -> Result<StackTrace<S::Str>, LogError> {
+    read_sized_string(source).map(StackTrace::new)
 }
 
-fn read_sized_string(
-    iter: &mut impl Iterator<Item = Result<u8, std::io::Error>>,
-) -> Result<String, LogError> {
-    let size: usize = read_until_whitespace(iter)?.parse()?;
-    let str_bytes: Vec<u8> = iter.take(size).collect::<Result<Vec<_>, _>>()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
 
-    // take byte (space) after string
-    iter.next();
+    const LOG: &str = "[LogFrameLocation] 19 https://example.com\n\
+        [LoadIC::Load_NOT_FOUND] 3 foo 11 at f (a:1:2)\n\
+        [LogFrameLocation] 21 https://example.com/b\n";
 
-    Ok(String::from_utf8(str_bytes)?)
+    fn write_log(contents: &[u8]) -> (tempdir::TempDir, PathBuf) {
+        let dir = tempdir::TempDir::new("ghunter4chrome-test").unwrap();
+        let path = dir.path().join("ghunter.log");
+        std::fs::write(&path, contents).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn parse_log_reads_every_record() {
+        let (_dir, path) = write_log(LOG.as_bytes());
+        let log = parse_log(&path).unwrap();
+        assert_eq!(log.messages.len(), 3);
+        assert!(log.diagnostics.is_empty());
+        assert_eq!(log.log_files[0].messages, 0..3);
+    }
+
+    #[test]
+    fn parse_log_since_starts_at_offset() {
+        let (_dir, path) = write_log(LOG.as_bytes());
+        let offset = LOG.find("[LoadIC").unwrap() as u64;
+        let log = parse_log_since(&path, offset, false).unwrap();
+        assert_eq!(log.messages.len(), 2);
+        assert!(matches!(
+            &log.messages[0],
+            LogMessage::UndefinedProperty { name, .. } if name == "foo"
+        ));
+
+        let log = parse_log_since(&path, LOG.len() as u64 + 10, false).unwrap();
+        assert!(log.messages.is_empty());
+    }
+
+    #[test]
+    fn truncated_last_record_is_skipped() {
+        let truncated = &LOG[..LOG.len() - 10];
+        let (_dir, path) = write_log(truncated.as_bytes());
+        let log = parse_log(&path).unwrap();
+        assert_eq!(log.messages.len(), 2);
+        assert_eq!(log.diagnostics.len(), 1);
+        assert_eq!(
+            log.diagnostics[0].offset,
+            LOG.rfind("[LogFrameLocation]").unwrap() as u64
+        );
+        assert_eq!(log.diagnostics[0].log_file.as_deref(), Some(path.as_path()));
+        assert_eq!(log.diagnostics[0].message, "unexpected end of log");
+    }
+
+    #[test]
+    fn malformed_record_fails_in_strict_mode() {
+        let log = LOG.replacen("3 foo", "x foo", 1);
+        let (_dir, path) = write_log(log.as_bytes());
+        let Err(LogError::InRecord { offset, record, .. }) = parse_log(&path) else {
+            panic!("expected a record error");
+        };
+        assert_eq!(offset, LOG.find("[LoadIC").unwrap() as u64);
+        assert_eq!(record.as_deref(), Some("LoadIC::Load_NOT_FOUND"));
+
+        let log = parse_log_lenient(&path).unwrap();
+        assert_eq!(log.messages.len(), 2);
+        assert_eq!(log.diagnostics.len(), 1);
+        assert_eq!(
+            log.diagnostics[0].message,
+            "invalid string size: invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn lenient_recovery_is_the_same_for_both_sources() {
+        // the size of the first string swallows the next record, which is found
+        // again after going back to the start of the malformed record
+        let log = LOG.replacen("3 foo", "300 foo", 1);
+        let borrowed = LogReader::from_slice(log.as_bytes())
+            .lenient(true)
+            .map(|message| message.unwrap().into_owned())
+            .collect::<Vec<_>>();
+        let streamed = LogReader::new(Cursor::new(log.as_bytes()))
+            .lenient(true)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(borrowed.len(), 2);
+        assert_eq!(borrowed, streamed);
+    }
+
+    #[test]
+    fn lenient_recovery_skips_brackets_in_strings() {
+        // the size of the trace is wrong, and the rest of the line looks like a
+        // record, which must not be parsed
+        let log = "[LoadIC::Load_NOT_FOUND] 3 abc x15 [object Object] 5 trace \n\
+            [LogFrameLocation] 20 https://example.com/\n";
+        let borrowed = LogReader::from_slice(log.as_bytes())
+            .lenient(true)
+            .map(|message| message.unwrap().into_owned())
+            .collect::<Vec<_>>();
+        let streamed = LogReader::new(Pipe {
+            data: log.as_bytes(),
+            chunk: 1,
+        })
+        .lenient(true)
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            borrowed,
+            [LogMessage::Location {
+                url: "https://example.com/".to_owned()
+            }]
+        );
+        assert_eq!(borrowed, streamed);
+    }
+
+    /// Reader that cannot seek and returns a few bytes at a time, like a pipe
+    struct Pipe<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Pipe<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let read = buf.len().min(self.chunk).min(self.data.len());
+            buf[..read].copy_from_slice(&self.data[..read]);
+            self.data = &self.data[read..];
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn streaming_from_any_reader() {
+        for seed in 0..20 {
+            let mut log = crate::log_generator::LogGenerator::new(seed).log(16 * 1024);
+            // corrupt a few sizes, so that lenient mode has to go back to records
+            // that are no longer in the last block read
+            let mut rng = fastrand::Rng::with_seed(seed);
+            for _ in 0..4 {
+                let i = rng.usize(..log.len());
+                if log[i].is_ascii_digit() {
+                    log[i] = b'9';
+                }
+            }
+
+            let borrowed = LogReader::from_slice(&log)
+                .lenient(true)
+                .map(|message| message.unwrap().into_owned())
+                .collect::<Vec<_>>();
+            for chunk in [1, 7, READ_BLOCK_SIZE] {
+                let streamed = LogReader::new(Pipe { data: &log, chunk })
+                    .lenient(true)
+                    .map(Result::unwrap)
+                    .collect::<Vec<_>>();
+                assert_eq!(borrowed, streamed, "seed {seed}, chunk {chunk}");
+            }
+        }
+    }
+
+    fn start(url: &str, top: bool) -> LogMessage {
+        LogMessage::DocumentStart {
+            url: Some(url.to_owned()),
+            is_top_frame: Some(top),
+        }
+    }
+
+    fn in_document(id: &str) -> LogMessage {
+        LogMessage::InDocument { id: id.to_owned() }
+    }
+
+    fn undefined(name: &str) -> LogMessage {
+        LogMessage::UndefinedProperty {
+            name: name.to_owned(),
+            source: PropertySource::InlineCache,
+            stack_trace: StackTrace::new(String::new()),
+        }
+    }
+
+    fn names<'a>(document: &DocumentLog<'a>) -> Vec<&'a str> {
+        document
+            .messages
+            .iter()
+            .filter_map(|msg| match msg {
+                LogMessage::UndefinedProperty { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn site_log(messages: Vec<LogMessage>) -> SiteLog {
+        SiteLog {
+            messages,
+            diagnostics: Vec::new(),
+            log_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn documents_follow_their_identifiers() {
+        let log = site_log(vec![
+            undefined("before"),
+            in_document("a"),
+            start("https://example.com/", true),
+            undefined("top"),
+            in_document("b"),
+            start("https://example.com/frame", false),
+            undefined("frame"),
+            // the top document logs again after its iframe has started
+            in_document("a"),
+            undefined("top again"),
+        ]);
+        let documents = log.documents();
+        assert_eq!(documents.len(), 3);
+
+        assert!(!documents[0].started);
+        assert_eq!(names(&documents[0]), ["before"]);
+
+        assert_eq!(documents[1].id, Some("a"));
+        assert_eq!(documents[1].url, Some("https://example.com/"));
+        assert_eq!(names(&documents[1]), ["top", "top again"]);
+
+        assert_eq!(documents[2].id, Some("b"));
+        assert_eq!(documents[2].is_top_frame, Some(false));
+        assert_eq!(names(&documents[2]), ["frame"]);
+    }
+
+    #[test]
+    fn documents_without_identifiers_follow_their_starts() {
+        let log = site_log(vec![
+            start("https://example.com/", true),
+            undefined("top"),
+            LogMessage::DocumentStart {
+                url: None,
+                is_top_frame: None,
+            },
+            LogMessage::Location {
+                url: "https://example.com/frame".to_owned(),
+            },
+            undefined("frame"),
+        ]);
+        let documents = log.documents();
+        assert_eq!(documents.len(), 2);
+        assert!(documents.iter().all(|document| document.started));
+        assert_eq!(names(&documents[0]), ["top"]);
+        assert_eq!(documents[1].url, Some("https://example.com/frame"));
+        assert_eq!(names(&documents[1]), ["frame"]);
+    }
+
+    #[test]
+    fn document_started_before_the_log() {
+        let log = site_log(vec![in_document("a"), undefined("a")]);
+        let documents = log.documents();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].id, Some("a"));
+        assert!(!documents[0].started);
+        assert_eq!(names(&documents[0]), ["a"]);
+    }
+
+    #[test]
+    fn documents_of_renderer_records_are_inferred_once_several_are_loaded() {
+        let log = site_log(vec![
+            in_document("a"),
+            start("https://example.com/", true),
+            undefined("top"),
+            in_document("b"),
+            start("about:blank", false),
+            // logged by the renderer for the top document, after the iframe started
+            undefined("top again"),
+            in_document("b"),
+            undefined("frame"),
+        ]);
+        let documents = log.documents();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].inferred, [false, false]);
+        assert_eq!(names(&documents[1]), ["top again", "frame"]);
+        assert_eq!(documents[1].inferred, [false, true, false]);
+    }
 }
@@ -0,0 +1,227 @@
+use std::io::{self, Write};
+
+use crate::log_parser::{LogMessage, PropertySource, ProxyAccessKind};
+
+/// Writes log messages in the ghunter log format, such that [`LogReader`] reads
+/// them back as is.
+///
+/// The only messages that cannot be written exactly are document starts with a URL
+/// but without frame information (written as child frames), and unknown records
+/// whose raw contents contain a line starting with `[`.
+///
+/// [`LogReader`]: crate::log_parser::LogReader
+pub struct LogWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> LogWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Write a single message as a record, ending with a newline
+    pub fn write<S: AsRef<str>>(&mut self, message: &LogMessage<S>) -> io::Result<()> {
+        match message {
+            LogMessage::UndefinedProperty {
+                name,
+                source,
+                stack_trace,
+            } => {
+                let record = match source {
+                    PropertySource::InlineCache => "LoadIC::Load_NOT_FOUND",
+                    PropertySource::RuntimeObject => "Runtime::GetObjectProperty_NOT_FOUND",
+                };
+                self.write_record_name(record)?;
+                self.write_sized_strings(&[name.as_ref(), stack_trace.raw.as_ref()])?;
+            }
+            LogMessage::AssignTaintedKey {
+                class_name,
+                key,
+                value,
+                source,
+                stack_trace,
+            } => {
+                let record = match source {
+                    PropertySource::InlineCache => "StoreIC::Store",
+                    PropertySource::RuntimeObject => "Runtime::SetObjectProperty_TAINTED",
+                };
+                self.write_record_name(record)?;
+                self.write_sized_strings(&[
+                    class_name.as_ref(),
+                    key.as_ref(),
+                    value.as_ref(),
+                    stack_trace.raw.as_ref(),
+                ])?;
+            }
+            LogMessage::Location { url } => {
+                self.write_record_name("LogFrameLocation")?;
+                self.write_sized_strings(&[url.as_ref()])?;
+            }
+            LogMessage::Polluted {
+                key,
+                value,
+                stack_trace,
+            } => {
+                self.write_record_name("Runtime::SetObjectProperty_PROTOTYPE")?;
+                self.write_sized_strings(&[
+                    key.as_ref(),
+                    value.as_ref(),
+                    stack_trace.raw.as_ref(),
+                ])?;
+            }
+            LogMessage::PrototypeGet {
+                key,
+                value,
+                stack_trace,
+            } => {
+                self.write_from_js("PROTOTYPE_GET")?;
+                self.write_sized_strings(&[
+                    key.as_ref(),
+                    value.as_ref(),
+                    stack_trace.raw.as_ref(),
+                ])?;
+            }
+            LogMessage::SinkReached {
+                sink_type,
+                value,
+                stack_trace,
+            } => {
+                self.write_record_name("LogIfStringTainted")?;
+                write!(self.writer, "{} ", sink_type.as_ref())?;
+                self.write_sized_strings(&[value.as_ref(), stack_trace.raw.as_ref()])?;
+            }
+            LogMessage::DocumentStart {
+                url: Some(url),
+                is_top_frame,
+            } => {
+                let frame = match is_top_frame {
+                    Some(true) => "top",
+                    _ => "child",
+                };
+                self.write_from_js("DOCUMENT_START")?;
+                self.write_sized_strings(&[url.as_ref(), frame])?;
+            }
+            LogMessage::DocumentStart { url: None, .. } => {
+                self.write_from_js("DOCUMENT_LOAD")?;
+            }
+            LogMessage::InDocument { id } => {
+                self.write_from_js("IN_DOCUMENT")?;
+                self.write_sized_strings(&[id.as_ref()])?;
+            }
+            LogMessage::ProxyAccess {
+                kind,
+                path,
+                value,
+                stack_trace,
+            } => {
+                let msg_type = match kind {
+                    ProxyAccessKind::Get => "PROXY_GET",
+                    ProxyAccessKind::Call => "PROXY_CALL",
+                    ProxyAccessKind::Construct => "PROXY_CONSTRUCT",
+                    ProxyAccessKind::Coerce => "PROXY_COERCE",
+                    ProxyAccessKind::Has => "PROXY_HAS",
+                };
+                self.write_from_js(msg_type)?;
+                self.write_sized_strings(&[
+                    path.as_ref(),
+                    value.as_ref(),
+                    stack_trace.raw.as_ref(),
+                ])?;
+            }
+            LogMessage::Unknown { name, subtype, raw } => {
+                self.write_record_name(name.as_ref())?;
+                if let Some(subtype) = subtype {
+                    write!(self.writer, "{} ", subtype.as_ref())?;
+                }
+                self.writer.write_all(raw.as_ref().as_bytes())?;
+            }
+        }
+
+        self.writer.write_all(b"\n")
+    }
+
+    fn write_record_name(&mut self, name: &str) -> io::Result<()> {
+        write!(self.writer, "[{}] ", name)
+    }
+
+    fn write_from_js(&mut self, msg_type: &str) -> io::Result<()> {
+        write!(self.writer, "[From_JS] {} ", msg_type)
+    }
+
+    /// Write each string prefixed with its size in bytes, as `<size> <string> `
+    fn write_sized_strings(&mut self, strings: &[&str]) -> io::Result<()> {
+        for s in strings {
+            write!(self.writer, "{} {} ", s.len(), s)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::{
+        log_generator::LogGenerator,
+        log_parser::{parse_log, LogReader},
+    };
+
+    fn encode(messages: &[LogMessage]) -> Vec<u8> {
+        let mut writer = LogWriter::new(Vec::new());
+        for message in messages {
+            writer.write(message).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    #[test]
+    fn round_trip_parse_log() {
+        let dir = tempdir::TempDir::new("ghunter4chrome-test").unwrap();
+        for seed in 0..200 {
+            let messages = LogGenerator::new(seed).messages(50);
+            let path = dir.path().join(format!("{seed}.log"));
+            std::fs::write(&path, encode(&messages)).unwrap();
+
+            let log = parse_log(&path).unwrap();
+            assert!(log.diagnostics.is_empty(), "seed {seed}");
+            assert_eq!(log.messages, messages, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn round_trip_log_reader() {
+        for seed in 0..200 {
+            let messages = LogGenerator::new(seed).messages(50);
+            let data = encode(&messages);
+
+            let borrowed = LogReader::from_slice(&data)
+                .map(|message| message.map(LogMessage::into_owned))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(borrowed, messages, "seed {seed}");
+
+            let streamed = LogReader::new(Cursor::new(data.as_slice()))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(streamed, messages, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn generated_log_parses() {
+        let data = LogGenerator::new(0).log(1024 * 1024);
+        assert!(data.len() >= 1024 * 1024);
+        for message in LogReader::from_slice(&data) {
+            message.unwrap();
+        }
+    }
+}
@@ -1,25 +1,45 @@
 use anyhow::{anyhow, bail, Context, Result};
-use clap::{Parser, Subcommand};
-use constants::POLLUTED_MARKER;
-use crawler::{gen_polluting_script, Crawler};
-use log_parser::{LogMessage, SiteLog};
+use async_std::{channel, task};
+use clap::{Parser, Subcommand, ValueEnum};
+use constants::{POLLUTED_MARKER, SOURCE_PAYLOAD_KEY_PREFIX};
+use crawler::{
+    gen_polluting_script, url_origin, Crash, Crawler, CrawlerOptions, PollutionValue,
+    SettleStrategy,
+};
+use exploration::ExplorationLimits;
+use failure::{Failure, RetryPolicy};
+use interaction::InteractionScript;
+use log_parser::{
+    parse_log, parse_log_lenient, DocumentLog, LogDiagnostic, LogMessage, ProxyAccessKind, SiteLog,
+};
+use output::{LogDumper, OutputFormat, OutputWriter};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use session::{load_cookie_jar, HeaderRule, Session};
+use sources::{gen_payload_url, PayloadPlacement, PayloadTechnique};
+use stack_trace::StackTrace;
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet, HashMap},
     fs::File,
-    io::{BufRead, BufReader, Write},
-    path::PathBuf,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::Duration,
 };
 use tempdir::TempDir;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use chromiumoxide::browser::{BrowserConfig, HeadlessMode};
+use ghunter4chromium_gadget_finder::{constants, log_parser, stack_trace};
 
-mod constants;
 mod crawler;
-mod log_parser;
+mod exploration;
+mod failure;
+mod interaction;
+mod output;
+mod session;
+mod sources;
 
 /// Find client-side prototype pollution gadgets in websites
 #[derive(Parser, Debug)]
@@ -42,6 +62,17 @@ struct Cli {
     #[arg(short = 'o', long)]
     output_json: Option<PathBuf>,
 
+    /// Path to a JSON Lines file where every parsed ghunter log record is written,
+    /// along with the URL (or log file) it belongs to.
+    #[arg(long, value_name = "PATH")]
+    dump_log: Option<PathBuf>,
+
+    /// Format of the output file. `json` writes a single array with one entry per URL,
+    /// while `json-lines` writes one entry per line, which is easier to process while
+    /// the run is still going. Both write each entry as soon as the URL has been processed.
+    #[arg(long, value_enum, default_value_t)]
+    output_format: OutputFormat,
+
     /// Path to the ghunter4chrome chromium executable. If not provided, the binary named
     /// `chromium-ghunter` present in PATH will be used.
     #[arg(long, env = "CHROMIUM_GHUNTER_EXECUTABLE")]
@@ -55,10 +86,124 @@ struct Cli {
     #[arg(short = 't', long, default_value_t = 5)]
     page_timeout: u64,
 
+    /// How to decide that a page has settled after loading, allowing the visit
+    /// to end before the page timeout.
+    #[arg(long, value_enum, default_value_t)]
+    settle: SettleStrategy,
+
+    /// How many milliseconds the ghunter log must stop growing for the page to be
+    /// considered settled with the `log-quiet` strategy.
+    #[arg(long, default_value_t = 1000)]
+    quiet_period: u64,
+
+    /// Wrap the taint marker of polluted properties in a proxy, logging every property
+    /// read, call, coercion and `in` check performed on it.
+    /// Only applicable to the `enumerable`, `custom` and `hunt` commands.
+    #[arg(long)]
+    proxy: bool,
+
+    /// Skip malformed records of the ghunter logs (e.g., truncated because the renderer
+    /// was killed) instead of failing, reporting them as diagnostics.
+    #[arg(long)]
+    lenient_logs: bool,
+
+    /// Also collect the logs of the other renderer processes that were active during
+    /// each visit (e.g., cross-site iframes and popups), found in the directory of the
+    /// page's log. Other browsers writing their logs to the same directory at the same
+    /// time cannot be told apart, so this is best used with a concurrency of 1.
+    /// These logs are always parsed leniently, and skipped if they cannot be parsed.
+    #[arg(long)]
+    merge_renderer_logs: bool,
+
+    /// Path to a cookie jar whose cookies are set before every visit, in the Netscape
+    /// format (as written by curl or wget) or in JSON (as exported by browser
+    /// extensions, Puppeteer or Playwright).
+    #[arg(long, value_name = "PATH")]
+    cookies: Option<PathBuf>,
+
+    /// Extra HTTP header to send with every request to the given domain and its
+    /// subdomains, or to every domain with `*`. Can be given multiple times.
+    #[arg(long = "header", value_parser = HeaderRule::parse, value_name = "DOMAIN:NAME: VALUE")]
+    headers: Vec<HeaderRule>,
+
+    /// Interaction script replayed in every visited page before observing it, e.g.,
+    /// to log in or open a modal. Either a JSON export of the Chrome DevTools
+    /// Recorder, or a list of steps in YAML (with a `.yaml` or `.yml` extension) or
+    /// JSON. The first `navigate` step of the script loads the visited URL.
+    #[arg(long, value_name = "PATH")]
+    interaction_script: Option<PathBuf>,
+
+    /// Explore every visited page before observing it, to trigger code that only runs
+    /// on user interaction: scroll down the page, hover and click its visible
+    /// interactive elements and fill its inputs with the taint marker. Navigations to
+    /// other origins and new windows are blocked.
+    #[arg(long)]
+    explore: bool,
+
+    /// How many scrolls, clicks and inputs to perform at most when exploring a page.
+    #[arg(long, default_value_t = 30)]
+    explore_max_actions: usize,
+
+    /// How many seconds exploring a page may take at most.
+    #[arg(long, default_value_t = 10)]
+    explore_timeout: u64,
+
+    /// Chromium profile directory to use instead of a fresh temporary one, e.g., to
+    /// reuse a logged in session. Only usable with a concurrency of 1, as a profile
+    /// cannot be used by several browsers at the same time.
+    #[arg(long, value_name = "PATH")]
+    profile_dir: Option<PathBuf>,
+
+    /// How many URLs to visit at the same time. Each concurrent visit runs in
+    /// its own browser instance.
+    #[arg(short = 'j', long, default_value_t = 1)]
+    concurrency: usize,
+
+    /// How many times to retry a URL after a transient failure (timeout, network
+    /// error, or HTTP 5xx or 429 with --fail-on-http-error). Crashes have their own
+    /// budget, see --crash-retries.
+    #[arg(long, default_value_t = 0)]
+    retries: usize,
+
+    /// How many times to retry a visit during which the browser or the renderer
+    /// crashed, independently of --retries.
+    #[arg(long, default_value_t = 1)]
+    crash_retries: usize,
+
+    /// How many milliseconds to wait before the first retry of a URL. The delay
+    /// doubles with every following retry.
+    #[arg(long, default_value_t = 1000)]
+    retry_backoff: u64,
+
+    /// Fail the visit of a page whose main navigation is answered with an HTTP error
+    /// status (4xx or 5xx), instead of observing it like any other page. The status
+    /// is reported in both cases.
+    #[arg(long)]
+    fail_on_http_error: bool,
+
+    /// Which frames to report findings from. Each finding is attributed to the
+    /// document that logged it from JavaScript, or else to the document that last
+    /// logged from JavaScript or started when it was logged. Findings logged by the
+    /// renderer itself while several documents were loaded in it may come from any
+    /// of them, so they are reported in every scope, without frame information.
+    #[arg(long, value_enum, default_value_t)]
+    frames: FrameScope,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum FrameScope {
+    /// All frames, including records logged before any document started.
+    #[default]
+    All,
+    /// Only documents loaded in the top frame.
+    Top,
+    /// Only documents with the same origin as the document of the top frame.
+    SameOrigin,
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// List all undefined properties in the webpage
@@ -70,48 +215,152 @@ enum Commands {
 
     /// Pollute the given properties.
     Custom {
-        /// The name (and possibly value, separated by =) of the property to pollute.
+        /// The name (and possibly type and value) of the property to pollute.
         /// Can be given multiple times.
         ///
         /// The value of the property will be a taint marker if not provided.
-        #[arg(short = 'p', long = "property", value_parser = Commands::parse_custom_property, value_name = "KEY[=VALUE]")]
-        properties: Vec<(String, Option<String>)>,
+        /// The type can be one of `string` (default), `number`, `boolean`, `json`,
+        /// `array`, `object` or `function` (e.g., `key:json={"a":1}`).
+        /// Without a value, `array` and `object` install a container holding the taint
+        /// marker and `function` installs a function returning the taint marker.
+        #[arg(short = 'p', long = "property", value_parser = Commands::parse_custom_property, value_name = "KEY[:TYPE][=VALUE]")]
+        properties: Vec<(String, PollutionValue)>,
+    },
+
+    /// List all undefined properties in the webpage, then pollute each of them
+    /// (one per visit) and report which sinks every property reaches.
+    Hunt,
+
+    /// Visit the webpage with prototype pollution payloads in the query string and
+    /// in the fragment (one per visit), reporting which of them pollute the prototype.
+    Sources,
+
+    /// Analyze previously saved ghunter log files instead of visiting webpages,
+    /// listing their undefined properties and reached sinks.
+    /// No browser is launched, so the URL options are ignored.
+    Analyze {
+        /// Path to a ghunter log file. Can be given multiple times.
+        #[arg(required = true, value_name = "LOG_FILE")]
+        log_files: Vec<PathBuf>,
     },
 }
 
+impl Cli {
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries,
+            crash_retries: self.crash_retries,
+            backoff: Duration::from_millis(self.retry_backoff),
+        }
+    }
+}
+
 impl Commands {
-    async fn run_action(&self, crawler: &Crawler, url: &str, output_json: &PathBuf) -> Result<()> {
+    async fn run_action(
+        &self,
+        crawler: &mut Crawler,
+        url: &str,
+        use_proxy: bool,
+        frames: FrameScope,
+    ) -> Result<ActionResult> {
         match self {
             Commands::Undefined => {
-                let json_result = find_website_undefined_properties(crawler, url).await?;
-                println!("{}", json_result);
-                
-                // Parse the JSON string to a Value and then write it prettily
-                let json_value: Value = serde_json::from_str(&json_result)?;
-                let file = File::create(output_json)?;
-                serde_json::to_writer_pretty(file, &json_value)?;
-                
-                Ok(())
+                let result = find_website_undefined_properties(crawler, url, frames).await?;
+                println!("{}", serde_json::to_string_pretty(&result)?);
+
+                Ok(ActionResult::Undefined(result))
             }
             Commands::Enumerable => {
-                find_sinks_from_custom_properties(
+                let result = find_sinks_from_custom_properties(
                     crawler,
                     url,
-                    &[(POLLUTED_MARKER.to_owned(), None)],
+                    &[(POLLUTED_MARKER.to_owned(), PollutionValue::Marker)],
+                    use_proxy,
+                    frames,
                 )
-                .await
+                .await?;
+
+                Ok(ActionResult::Sinks(result))
             }
             Commands::Custom { properties } => {
-                find_sinks_from_custom_properties(crawler, url, properties).await
+                let result =
+                    find_sinks_from_custom_properties(crawler, url, properties, use_proxy, frames)
+                        .await?;
+
+                Ok(ActionResult::Sinks(result))
+            }
+            Commands::Hunt => {
+                let result = hunt_gadgets(crawler, url, use_proxy, frames).await?;
+
+                Ok(ActionResult::Hunt(result))
+            }
+            Commands::Sources => {
+                let result = find_pollution_sources(crawler, url, frames).await?;
+
+                Ok(ActionResult::Sources(result))
             }
+            Commands::Analyze { .. } => bail!("log files cannot be analyzed while crawling"),
         }
     }
-    fn parse_custom_property(s: &str) -> Result<(String, Option<String>), String> {
-        if let Some((key, value)) = s.split_once('=') {
-            Ok((key.to_string(), Some(value.to_string())))
-        } else {
-            Ok((s.to_string(), None))
-        }
+
+    fn parse_custom_property(s: &str) -> Result<(String, PollutionValue), String> {
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (s, None),
+        };
+        // keys containing ':' are still allowed, as long as what follows is not a type
+        let (key, value_type) = match key.rsplit_once(':') {
+            Some((key, value_type)) if Self::is_value_type(value_type) => (key, value_type),
+            _ => (key, "string"),
+        };
+
+        let parse_json = |value: &str| -> Result<Value, String> {
+            serde_json::from_str(value).map_err(|e| format!("invalid {} value: {}", value_type, e))
+        };
+
+        let value = match (value_type, value) {
+            ("string" | "str", None) => PollutionValue::Marker,
+            ("string" | "str", Some(value)) => PollutionValue::Value(Value::String(value.into())),
+            ("array", None) => PollutionValue::TaintedArray,
+            ("object", None) => PollutionValue::TaintedObject,
+            ("function" | "fn", value) => {
+                PollutionValue::Function(value.map(parse_json).transpose()?)
+            }
+            (_, None) => return Err(format!("a value is required for type {}", value_type)),
+            (_, Some(value)) => {
+                let value = parse_json(value)?;
+                let matches_type = match value_type {
+                    "number" | "num" => value.is_number(),
+                    "boolean" | "bool" => value.is_boolean(),
+                    "array" => value.is_array(),
+                    "object" => value.is_object(),
+                    _ => true,
+                };
+                if !matches_type {
+                    return Err(format!("value {} is not of type {}", value, value_type));
+                }
+                PollutionValue::Value(value)
+            }
+        };
+
+        Ok((key.to_string(), value))
+    }
+
+    fn is_value_type(value_type: &str) -> bool {
+        matches!(
+            value_type,
+            "string"
+                | "str"
+                | "number"
+                | "num"
+                | "boolean"
+                | "bool"
+                | "json"
+                | "array"
+                | "object"
+                | "function"
+                | "fn"
+        )
     }
 }
 
@@ -119,6 +368,25 @@ impl Commands {
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
     let cli = Cli::parse();
+    let retry_policy = cli.retry_policy();
+
+    let output_json = match cli.output_json {
+        Some(path) => path,
+        None => PathBuf::from("output.json"),
+    };
+
+    let log_dumper = cli.dump_log.as_deref().map(LogDumper::create).transpose()?;
+
+    if let Commands::Analyze { log_files } = &cli.command {
+        return analyze_log_files(
+            log_files,
+            &output_json,
+            cli.output_format,
+            cli.lenient_logs,
+            cli.frames,
+            log_dumper.as_ref(),
+        );
+    }
 
     let urls = if let Some(url_file) = cli.url_file {
         let file = File::open(url_file)?;
@@ -146,57 +414,248 @@ async fn main() -> Result<()> {
             .context("failed to get chromium-ghunter executable in PATH")?,
     };
 
-    let user_data_dir = TempDir::new("ghunter4chrome-gadget-finder")
-        .context("failed to create temporary directory for browser user data")?;
-
     let headless_mode = if cli.headful {
         HeadlessMode::False
     } else {
         HeadlessMode::New
     };
 
-    let browser_config = BrowserConfig::builder()
-        .chrome_executable(executable)
-        .user_data_dir(user_data_dir)
-        .headless_mode(headless_mode)
-        .build()
-        .map_err(|e| anyhow!(e))?;
-
-    let crawler = Crawler::new(browser_config, cli.page_timeout)
-        .await
-        .context("failed to create crawler instance")?;
+    let mut output = OutputWriter::create(&output_json, cli.output_format)?;
 
-    let output_json = match cli.output_json {
-        Some(path) => path,
-        None => PathBuf::from("output.json"),
+    let concurrency = cli.concurrency.clamp(1, urls.len());
+    if cli.merge_renderer_logs && concurrency > 1 {
+        warn!(
+            "merging renderer logs with a concurrency above 1 may mix up logs of different pages"
+        );
+    }
+    if cli.profile_dir.is_some() && concurrency > 1 {
+        bail!("a profile directory can only be used with a concurrency of 1");
+    }
+    let crawler_options = CrawlerOptions {
+        page_timeout: cli.page_timeout,
+        settle_strategy: cli.settle,
+        quiet_period: Duration::from_millis(cli.quiet_period),
+        lenient_logs: cli.lenient_logs,
+        merge_renderer_logs: cli.merge_renderer_logs,
+        log_dumper: log_dumper.clone(),
+        session: Session {
+            cookies: match &cli.cookies {
+                Some(path) => load_cookie_jar(path)?,
+                None => Vec::new(),
+            },
+            headers: cli.headers,
+        },
+        interaction: cli
+            .interaction_script
+            .as_deref()
+            .map(InteractionScript::load)
+            .transpose()?,
+        exploration: cli.explore.then(|| ExplorationLimits {
+            max_actions: cli.explore_max_actions,
+            timeout: Duration::from_secs(cli.explore_timeout),
+        }),
+        fail_on_http_error: cli.fail_on_http_error,
+        retry_policy,
     };
+    let command = Arc::new(cli.command);
+    let use_proxy = cli.proxy;
+    let frames = cli.frames;
 
+    let (url_sender, url_receiver) = channel::unbounded::<String>();
     for url in urls {
-        info!(url = url, "visiting website");
-        if let Err(error) = cli.command.run_action(&crawler, &url, &output_json).await {
-            error!(url = url, "failed while visiting website {:?}", error);
+        url_sender.send(url).await?;
+    }
+    url_sender.close();
+
+    let (report_sender, report_receiver) = channel::unbounded::<UrlReport>();
+
+    // each worker gets its own browser process (and user data dir), ensuring
+    // the ghunter logs of pages visited at the same time never get mixed up
+    let mut workers = Vec::with_capacity(concurrency);
+    for worker_id in 0..concurrency {
+        let (temp_dir, user_data_dir) = match &cli.profile_dir {
+            Some(profile_dir) => (None, profile_dir.clone()),
+            None => {
+                let temp_dir = TempDir::new("ghunter4chrome-gadget-finder")
+                    .context("failed to create temporary directory for browser user data")?;
+                let user_data_dir = temp_dir.path().to_path_buf();
+                (Some(temp_dir), user_data_dir)
+            }
         };
+
+        let browser_config = BrowserConfig::builder()
+            .chrome_executable(&executable)
+            .user_data_dir(user_data_dir)
+            .headless_mode(headless_mode)
+            .build()
+            .map_err(|e| anyhow!(e))?;
+
+        let mut crawler = Crawler::new(browser_config, crawler_options.clone())
+            .await
+            .context("failed to create crawler instance")?;
+
+        let command = Arc::clone(&command);
+        let url_receiver = url_receiver.clone();
+        let report_sender = report_sender.clone();
+
+        workers.push(task::spawn(async move {
+            while let Ok(url) = url_receiver.recv().await {
+                info!(worker = worker_id, url = url, "visiting website");
+                let mut failures: Vec<Failure> = Vec::new();
+                let result = loop {
+                    let error = match command
+                        .run_action(&mut crawler, &url, use_proxy, frames)
+                        .await
+                    {
+                        Ok(result) => break Some(result),
+                        Err(error) => error,
+                    };
+                    let failure = Failure::from_error(&error);
+                    let retry = failures.len() < retry_policy.retries && failure.should_retry();
+                    error!(
+                        url = url,
+                        kind = ?failure.kind,
+                        retry = retry,
+                        "failed while visiting website {:?}",
+                        error
+                    );
+                    let delay = retry_policy.delay(failures.len());
+                    failures.push(failure);
+                    if !retry {
+                        break None;
+                    }
+                    task::sleep(delay).await;
+                };
+                let report = UrlReport {
+                    url,
+                    error: match result {
+                        Some(_) => None,
+                        None => failures.last().map(|failure| failure.message.clone()),
+                    },
+                    result,
+                    http_status: crawler.take_http_status(),
+                    failures,
+                    crashes: crawler.take_crashes(),
+                    diagnostics: crawler.take_log_diagnostics(),
+                };
+                if report_sender.send(report).await.is_err() {
+                    break;
+                }
+            }
+
+            let result = crawler
+                .close()
+                .await
+                .context("failed to close the crawler instance");
+            drop(temp_dir);
+            result
+        }));
+    }
+    drop(report_sender);
+
+    let mut written = Ok(());
+    while let Ok(report) = report_receiver.recv().await {
+        written = output.write(&report);
+        if written.is_err() {
+            break;
+        }
     }
+    // closing the channel makes the workers stop and close their browsers
+    drop(report_receiver);
+    let written = written.and_then(|_| output.finish());
 
-    crawler
-        .close()
-        .await
-        .context("failed to close the crawler instance")?;
+    for worker in workers {
+        worker.await?;
+    }
+    written.context("failed to write output file")?;
+    if let Some(log_dumper) = log_dumper {
+        log_dumper.flush()?;
+    }
     Ok(())
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ActionResult {
+    Undefined(UndefinedPropertiesResult),
+    Sinks(SinksResult),
+    Hunt(HuntResult),
+    Sources(SourcesResult),
+}
+
+/// Entry of the output file for a single visited URL
+#[derive(Serialize, Deserialize)]
+struct UrlReport {
+    url: String,
+    result: Option<ActionResult>,
+    /// Set when processing the URL failed
+    error: Option<String>,
+    /// HTTP status of the first visit of the URL
+    http_status: Option<u16>,
+    /// Every failed attempt at processing the URL, the last one being the
+    /// reason for `error` if set
+    failures: Vec<Failure>,
+    /// Browser and renderer crashes while processing the URL, each telling
+    /// whether the visit was retried after it
+    crashes: Vec<Crash>,
+    /// Malformed log records skipped while parsing the logs of the visits,
+    /// in lenient mode or because they were truncated
+    diagnostics: Vec<LogDiagnostic>,
+}
+
+/// Entry of the output file for a single analyzed log file
+#[derive(Serialize, Deserialize)]
+struct LogFileReport {
+    log_file: PathBuf,
+    result: Option<LogAnalysisResult>,
+    /// Set when parsing the log file failed
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LogAnalysisResult {
+    undefined_properties: Vec<UndefinedProperty>,
+    sinks: Vec<SinkEntry>,
+    proxy_accesses: Vec<ProxyAccessEntry>,
+    /// Records not understood by the parser, e.g., from new instrumentation
+    unknown_records: Vec<UnknownRecord>,
+    /// Number of malformed records skipped, only in lenient mode
+    skipped_records: usize,
+    diagnostics: Vec<LogDiagnostic>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct UnknownRecord {
+    name: String,
+    /// Message type of `From_JS` records
+    subtype: Option<String>,
+    raw: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
 struct PropertyLocation {
     filepath: String,
     line: usize,
     column: usize,
 }
 
+/// Document a finding was logged in. Only the log file is known for records
+/// logged by the renderer itself while several documents were loaded in it.
+#[derive(Serialize, Deserialize, Clone, Default)]
+struct FrameInfo {
+    /// Log file of the renderer process the document was loaded in
+    log_file: Option<PathBuf>,
+    url: Option<String>,
+    origin: Option<String>,
+    is_top_frame: Option<bool>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct UndefinedProperty {
     name: String,
     location: Option<PropertyLocation>,
-    stack_trace: String,
+    stack_trace: StackTrace,
+    frame: FrameInfo,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -206,46 +665,389 @@ struct UndefinedPropertiesResult {
     count: usize,
 }
 
-async fn find_website_undefined_properties(crawler: &Crawler, url: &str) -> Result<String> {
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SinkEntry {
+    SinkReached {
+        sink_type: String,
+        value: String,
+        location: Option<PropertyLocation>,
+        stack_trace: StackTrace,
+        frame: FrameInfo,
+        /// Reads of polluted properties whose taint markers flowed into this sink
+        sources: Vec<TaintSource>,
+    },
+    AssignTaintedKey {
+        class_name: String,
+        key: String,
+        value: String,
+        location: Option<PropertyLocation>,
+        stack_trace: StackTrace,
+        frame: FrameInfo,
+        /// Reads of polluted properties whose taint markers flowed into this sink
+        sources: Vec<TaintSource>,
+    },
+}
+
+/// A read of a polluted property, identified by the taint marker it returned
+#[derive(Serialize, Deserialize)]
+struct TaintSource {
+    /// The full taint marker, e.g., `0xEFFACED:0:3`
+    marker: String,
+    /// Index of the polluted property in the list of polluted properties
+    property_index: usize,
+    /// How many times the polluted property had been read before this read
+    access_index: usize,
+    /// The remaining fields are only present if the read has been logged
+    property: Option<String>,
+    location: Option<PropertyLocation>,
+    stack_trace: Option<StackTrace>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProxyAccessEntry {
+    kind: ProxyAccessKind,
+    /// Access path starting at the polluted property, e.g., `polluted.x()`
+    path: String,
+    value: String,
+    location: Option<PropertyLocation>,
+    stack_trace: StackTrace,
+    frame: FrameInfo,
+}
+
+/// Everything logged during a visit that shows where polluted values ended up
+struct PollutionFindings {
+    sinks: Vec<SinkEntry>,
+    proxy_accesses: Vec<ProxyAccessEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PollutedProperty {
+    name: String,
+    value: PollutionValue,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SinksResult {
+    url: String,
+    polluted_properties: Vec<PollutedProperty>,
+    sinks: Vec<SinkEntry>,
+    count: usize,
+    /// Only populated when polluting with proxies
+    proxy_accesses: Vec<ProxyAccessEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HuntCandidate {
+    property: String,
+    /// Locations where the property was read while still undefined
+    undefined_at: Vec<PropertyLocation>,
+    sinks: Vec<SinkEntry>,
+    /// Only populated when polluting with proxies
+    proxy_accesses: Vec<ProxyAccessEntry>,
+    /// Set when visiting the page with this property polluted failed
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HuntResult {
+    url: String,
+    candidates: Vec<HuntCandidate>,
+    /// Number of candidates that reached at least one sink
+    gadget_count: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PollutionEntry {
+    key: String,
+    value: String,
+    location: Option<PropertyLocation>,
+    stack_trace: StackTrace,
+    frame: FrameInfo,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SourcePayloadResult {
+    technique: PayloadTechnique,
+    placement: PayloadPlacement,
+    visited_url: String,
+    key: String,
+    value: String,
+    /// Prototype assignments of the payload's key and value
+    pollutions: Vec<PollutionEntry>,
+    /// Set when visiting the page with this payload failed
+    error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SourcesResult {
+    url: String,
+    payloads: Vec<SourcePayloadResult>,
+    /// Number of payloads that polluted the prototype
+    source_count: usize,
+}
+
+/// Run the analyses of all commands on saved log files, without launching a browser.
+fn analyze_log_files(
+    log_files: &[PathBuf],
+    output_json: &Path,
+    output_format: OutputFormat,
+    lenient_logs: bool,
+    frames: FrameScope,
+    log_dumper: Option<&LogDumper>,
+) -> Result<()> {
+    let mut output = OutputWriter::create(output_json, output_format)?;
+
+    for log_file in log_files {
+        info!(log_file = ?log_file, "analyzing log file");
+        let log = if lenient_logs {
+            parse_log_lenient(log_file)
+        } else {
+            parse_log(log_file)
+        };
+        let report = match log {
+            Ok(log) => {
+                if let Some(log_dumper) = log_dumper {
+                    if let Err(error) = log_dumper.dump(&log_file.to_string_lossy(), &log) {
+                        error!(log_file = ?log_file, "failed while dumping log file {:?}", error);
+                    }
+                }
+                let undefined_properties = find_undefined_properties_in_log(&log, frames);
+                let findings = find_pollution_findings_in_log(&log, frames);
+                info!("found {} relevant log entries", findings.sinks.len());
+                LogFileReport {
+                    log_file: log_file.clone(),
+                    result: Some(LogAnalysisResult {
+                        undefined_properties,
+                        sinks: findings.sinks,
+                        proxy_accesses: findings.proxy_accesses,
+                        unknown_records: find_unknown_records_in_log(&log),
+                        skipped_records: log.diagnostics.len(),
+                        diagnostics: log.diagnostics,
+                    }),
+                    error: None,
+                }
+            }
+            Err(error) => {
+                error!(log_file = ?log_file, "failed while parsing log file {:?}", error);
+                LogFileReport {
+                    log_file: log_file.clone(),
+                    result: None,
+                    error: Some(error.to_string()),
+                }
+            }
+        };
+        output.write(&report)?;
+    }
+
+    if let Some(log_dumper) = log_dumper {
+        log_dumper.flush()?;
+    }
+    output.finish().context("failed to write output file")
+}
+
+fn find_unknown_records_in_log(log: &SiteLog) -> Vec<UnknownRecord> {
+    log.messages
+        .iter()
+        .filter_map(|msg| match msg {
+            LogMessage::Unknown { name, subtype, raw } => Some(UnknownRecord {
+                name: name.clone(),
+                subtype: subtype.clone(),
+                raw: raw.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+async fn find_website_undefined_properties(
+    crawler: &mut Crawler,
+    url: &str,
+    frames: FrameScope,
+) -> Result<UndefinedPropertiesResult> {
     let log = crawler.visit_url(url, None).await?;
 
-    let undefined_properties = get_all_undefined_properties_and_stack_traces(&log);
+    let properties = find_undefined_properties_in_log(&log, frames);
+    let count = properties.len();
+    Ok(UndefinedPropertiesResult {
+        url: url.to_string(),
+        properties,
+        count,
+    })
+}
+
+fn find_undefined_properties_in_log(log: &SiteLog, frames: FrameScope) -> Vec<UndefinedProperty> {
+    let undefined_properties = get_all_undefined_properties_and_stack_traces(log, frames);
     let mut result_properties = Vec::new();
 
     info!("found {} undefined properties", undefined_properties.len());
-    for (name, stack_trace) in undefined_properties {
+    for ((name, _, _), (stack_trace, frame)) in undefined_properties {
         info!(name = name, "undefined property");
-        let location = match find_line_number_column_number(&stack_trace).await {
-            Ok((filepath, line, column)) => {
-                let normalized_filepath = normalize_path(&filepath);
-                info!(filepath = normalized_filepath, line = line, column = column);
-                Some(PropertyLocation {
-                    filepath: normalized_filepath,
-                    line,
-                    column,
-                })
-            }
-            Err(_err) => {
-                info!(stack_trace = stack_trace, "parse head error stack trace");
-                None
-            }
-        };
+        let location = find_property_location(stack_trace);
+        match &location {
+            Some(location) => info!(
+                filepath = location.filepath,
+                line = location.line,
+                column = location.column
+            ),
+            None => info!(
+                stack_trace = stack_trace.raw,
+                "no script location in stack trace"
+            ),
+        }
 
         result_properties.push(UndefinedProperty {
-            name,
+            name: name.to_string(),
             location,
-            stack_trace,
+            stack_trace: stack_trace.clone(),
+            frame,
+        });
+    }
+
+    result_properties
+}
+
+/// Run the whole GHunter workflow on a single page: find the undefined properties,
+/// then visit the page again once per property with that property polluted,
+/// collecting the sinks the taint marker reaches.
+async fn hunt_gadgets(
+    crawler: &mut Crawler,
+    url: &str,
+    use_proxy: bool,
+    frames: FrameScope,
+) -> Result<HuntResult> {
+    let undefined = find_website_undefined_properties(crawler, url, frames).await?;
+
+    let mut undefined_at: BTreeMap<String, BTreeSet<PropertyLocation>> = BTreeMap::new();
+    for property in undefined.properties {
+        let locations = undefined_at.entry(property.name).or_default();
+        locations.extend(property.location);
+    }
+
+    info!("hunting {} candidate properties", undefined_at.len());
+    let mut candidates = Vec::with_capacity(undefined_at.len());
+    for (property, locations) in undefined_at {
+        info!(property = property, "polluting candidate property");
+        let properties = [(property.clone(), PollutionValue::Marker)];
+        let (findings, error) =
+            match collect_pollution_findings(crawler, url, &properties, use_proxy, frames).await {
+                Ok(findings) => (findings, None),
+                Err(err) => {
+                    error!(
+                        property = property,
+                        "failed while polluting property {:?}", err
+                    );
+                    let findings = PollutionFindings {
+                        sinks: Vec::new(),
+                        proxy_accesses: Vec::new(),
+                    };
+                    (findings, Some(format!("{:#}", err)))
+                }
+            };
+        info!(
+            property = property,
+            "reached {} sinks",
+            findings.sinks.len()
+        );
+
+        candidates.push(HuntCandidate {
+            property,
+            undefined_at: locations.into_iter().collect(),
+            sinks: findings.sinks,
+            proxy_accesses: findings.proxy_accesses,
+            error,
         });
     }
 
-    let properties_count = result_properties.len();
-    let result = UndefinedPropertiesResult {
+    let gadget_count = candidates.iter().filter(|c| !c.sinks.is_empty()).count();
+    info!("found {} gadget candidates", gadget_count);
+
+    Ok(HuntResult {
         url: url.to_string(),
-        properties: result_properties,
-        count: properties_count,
-    };
+        candidates,
+        gadget_count,
+    })
+}
+
+/// Visit the page once per pollution payload, in both the query string and the fragment,
+/// and find out which of them end up polluting the prototype.
+async fn find_pollution_sources(
+    crawler: &mut Crawler,
+    url: &str,
+    frames: FrameScope,
+) -> Result<SourcesResult> {
+    let mut payloads = Vec::new();
+    for placement in PayloadPlacement::ALL {
+        for technique in PayloadTechnique::ALL {
+            // use a different key and value for each payload, so that a pollution
+            // can never be wrongly attributed to another payload
+            let key = format!("{}{}", SOURCE_PAYLOAD_KEY_PREFIX, payloads.len());
+            let value = format!("{}{}", POLLUTED_MARKER, payloads.len());
+            let visited_url = gen_payload_url(url, technique, placement, &key, &value);
+
+            info!(url = visited_url, "visiting website with pollution payload");
+            let (pollutions, error) = match crawler.visit_url(&visited_url, None).await {
+                Ok(log) => (find_pollutions(&log, &key, &value, frames), None),
+                Err(err) => {
+                    error!(
+                        url = visited_url,
+                        "failed while visiting website with pollution payload {:?}", err
+                    );
+                    (Vec::new(), Some(format!("{:#}", err)))
+                }
+            };
+
+            payloads.push(SourcePayloadResult {
+                technique,
+                placement,
+                visited_url,
+                key,
+                value,
+                pollutions,
+                error,
+            });
+        }
+    }
 
-    Ok(serde_json::to_string_pretty(&result)?)
+    let source_count = payloads.iter().filter(|p| !p.pollutions.is_empty()).count();
+    info!("found {} pollution sources", source_count);
+
+    Ok(SourcesResult {
+        url: url.to_string(),
+        payloads,
+        source_count,
+    })
+}
+
+fn find_pollutions(
+    log: &SiteLog,
+    payload_key: &str,
+    payload_value: &str,
+    frames: FrameScope,
+) -> Vec<PollutionEntry> {
+    let mut pollutions = Vec::new();
+    for selected in select_documents(log, frames) {
+        for (msg, frame) in selected.records() {
+            if let LogMessage::Polluted {
+                key,
+                value,
+                stack_trace,
+            } = msg
+            {
+                if key == payload_key && value == payload_value {
+                    pollutions.push(PollutionEntry {
+                        key: key.clone(),
+                        value: value.clone(),
+                        location: find_property_location(stack_trace),
+                        stack_trace: stack_trace.clone(),
+                        frame: frame.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    pollutions
 }
 
 fn normalize_path(path: &str) -> String {
@@ -270,82 +1072,617 @@ fn normalize_path(path: &str) -> String {
     result
 }
 
-async fn find_line_number_column_number(trace: &str) -> Result<(String, usize, usize)> {
-    let mut iter = trace.split('\n');
-    let head = iter.next().unwrap();
-    let re = Regex::new(r"[\(]?(?:https?://)?([^\s:]+):(\d+):(\d+)[\)]?").unwrap();
-    if let Some(caps) = re.captures(head) {
-        let url = &caps[1];
-        let line: usize = caps[2].parse().unwrap();
-        let column: usize = caps[3].parse().unwrap();
-        Ok((url.to_string(), line, column))
-    } else {
-        Err(anyhow!("failed to find line number and column number"))
+/// Map each distinct undefined property name, raw stack trace and document URL
+/// (if known) to the parsed stack trace and the document it was logged in
+fn get_all_undefined_properties_and_stack_traces(
+    log: &SiteLog,
+    frames: FrameScope,
+) -> BTreeMap<(&str, &str, Option<String>), (&StackTrace, FrameInfo)> {
+    let mut undefined_properties = BTreeMap::new();
+    // we only care about the log when it opens the actual page, so skip the messages
+    // logged before any document started
+    for selected in select_documents(log, frames) {
+        if !selected.document.started {
+            continue;
+        }
+        for (msg, frame) in selected.records() {
+            if let LogMessage::UndefinedProperty {
+                name, stack_trace, ..
+            } = msg
+            {
+                undefined_properties.insert(
+                    (name.as_str(), stack_trace.raw.as_str(), frame.url.clone()),
+                    (stack_trace, frame.clone()),
+                );
+            }
+        }
     }
+
+    undefined_properties
 }
 
-fn get_all_undefined_properties(log: &SiteLog) -> BTreeSet<String> {
-    // we only care about the log when it opens the actual page, so skip messages until a
-    // frame location is logged
-    let mut undefined_properties = BTreeSet::new();
-    for msg in log
-        .messages
-        .iter()
-        .skip_while(|msg| !matches!(msg, log_parser::LogMessage::DocumentStart))
-    {
-        if let LogMessage::UndefinedProperty { name, .. } = msg {
-            undefined_properties.insert(name.clone());
+/// Visit the page with the given properties polluted and return every sink
+/// related log entry, as well as every access to a proxy-backed taint value.
+async fn collect_pollution_findings(
+    crawler: &mut Crawler,
+    url: &str,
+    properties: &[(String, PollutionValue)],
+    use_proxy: bool,
+    frames: FrameScope,
+) -> Result<PollutionFindings> {
+    let polluting_script = gen_polluting_script(properties, use_proxy);
+    let log = crawler.visit_url(url, Some(&polluting_script)).await?;
+
+    Ok(find_pollution_findings_in_log(&log, frames))
+}
+
+fn find_pollution_findings_in_log(log: &SiteLog, frames: FrameScope) -> PollutionFindings {
+    let mut sinks = Vec::new();
+    let mut proxy_accesses = Vec::new();
+    for selected in select_documents(log, frames) {
+        // access indexes are counted per document, so markers are only unique
+        // within the document that read the polluted property
+        let prototype_gets = index_prototype_gets(&selected.document.messages);
+        for (msg, frame) in selected.records() {
+            if is_sink_related(msg) {
+                sinks.push(sink_entry_from_log_message(msg, &prototype_gets, frame));
+            }
+
+            if let LogMessage::ProxyAccess {
+                kind,
+                path,
+                value,
+                stack_trace,
+            } = msg
+            {
+                proxy_accesses.push(ProxyAccessEntry {
+                    kind: *kind,
+                    path: path.clone(),
+                    value: value.clone(),
+                    location: find_property_location(stack_trace),
+                    stack_trace: stack_trace.clone(),
+                    frame: frame.clone(),
+                });
+            }
         }
     }
 
-    undefined_properties
+    PollutionFindings {
+        sinks,
+        proxy_accesses,
+    }
+}
+
+fn sink_entry_from_log_message(
+    msg: &LogMessage,
+    prototype_gets: &HashMap<&str, (&str, &StackTrace)>,
+    frame: &FrameInfo,
+) -> SinkEntry {
+    match msg {
+        LogMessage::SinkReached {
+            sink_type,
+            value,
+            stack_trace,
+        } => SinkEntry::SinkReached {
+            sink_type: sink_type.clone(),
+            value: value.clone(),
+            location: find_property_location(stack_trace),
+            stack_trace: stack_trace.clone(),
+            frame: frame.clone(),
+            sources: find_taint_sources(&[value], prototype_gets),
+        },
+        LogMessage::AssignTaintedKey {
+            class_name,
+            key,
+            value,
+            stack_trace,
+            ..
+        } => SinkEntry::AssignTaintedKey {
+            class_name: class_name.clone(),
+            key: key.clone(),
+            value: value.clone(),
+            location: find_property_location(stack_trace),
+            stack_trace: stack_trace.clone(),
+            frame: frame.clone(),
+            sources: find_taint_sources(&[key, value], prototype_gets),
+        },
+        _ => unreachable!("only sink related log entries can be converted"),
+    }
 }
 
-fn get_all_undefined_properties_and_stack_traces(log: &SiteLog) -> BTreeSet<(String, String)> {
-    let mut undefined_properties = BTreeSet::new();
-    for msg in log
-        .messages
+/// Map the value returned by each read of a polluted property (i.e., its taint marker)
+/// to the name of the property and the stack trace of the read, for the messages of
+/// a single document.
+fn index_prototype_gets<'a>(
+    messages: &[&'a LogMessage],
+) -> HashMap<&'a str, (&'a str, &'a StackTrace)> {
+    messages
         .iter()
-        .skip_while(|msg| !matches!(msg, log_parser::LogMessage::DocumentStart))
-    {
-        if let LogMessage::UndefinedProperty {
-            name, stack_trace, ..
-        } = msg
-        {
-            undefined_properties.insert((name.clone(), stack_trace.clone()));
+        .filter_map(|msg| match msg {
+            LogMessage::PrototypeGet {
+                key,
+                value,
+                stack_trace,
+            } => Some((value.as_str(), (key.as_str(), stack_trace))),
+            _ => None,
+        })
+        .collect()
+}
+
+static TAINT_MARKER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"{}:(\d+):(\d+)", regex::escape(POLLUTED_MARKER))).unwrap()
+});
+
+/// Decode every taint marker (`MARKER:propertyIndex:accessIndex`) present in the given
+/// values and join it with the read of the polluted property that returned it.
+fn find_taint_sources(
+    values: &[&String],
+    prototype_gets: &HashMap<&str, (&str, &StackTrace)>,
+) -> Vec<TaintSource> {
+    let mut markers = BTreeSet::new();
+    for value in values {
+        for caps in TAINT_MARKER_RE.captures_iter(value) {
+            let (Ok(property_index), Ok(access_index)) = (caps[1].parse(), caps[2].parse()) else {
+                continue;
+            };
+            markers.insert((property_index, access_index, caps[0].to_string()));
         }
     }
 
-    undefined_properties
+    let mut sources = Vec::with_capacity(markers.len());
+    for (property_index, access_index, marker) in markers {
+        let read = prototype_gets.get(marker.as_str());
+        let location = read.and_then(|(_, stack_trace)| find_property_location(stack_trace));
+        sources.push(TaintSource {
+            property_index,
+            access_index,
+            property: read.map(|(property, _)| property.to_string()),
+            location,
+            stack_trace: read.map(|(_, stack_trace)| (*stack_trace).clone()),
+            marker,
+        });
+    }
+
+    sources
+}
+
+/// Location of the innermost frame of the stack trace that belongs to a script of
+/// the page, skipping builtins and the polluting script
+fn find_property_location(stack_trace: &StackTrace) -> Option<PropertyLocation> {
+    let frame = stack_trace.top_script_frame()?;
+    let script_url = frame.script_url?;
+    // reported without the scheme, as locations always have been
+    let script_url = script_url
+        .strip_prefix("https://")
+        .or_else(|| script_url.strip_prefix("http://"))
+        .unwrap_or(script_url);
+    Some(PropertyLocation {
+        filepath: normalize_path(script_url),
+        line: frame.line? as usize,
+        column: frame.column.unwrap_or_default() as usize,
+    })
 }
 
 async fn find_sinks_from_custom_properties(
-    crawler: &Crawler,
+    crawler: &mut Crawler,
     url: &str,
-    properties: &[(String, Option<String>)],
-) -> Result<()> {
-    let polluting_script = gen_polluting_script(properties);
-    let log = crawler.visit_url(url, Some(&polluting_script)).await?;
+    properties: &[(String, PollutionValue)],
+    use_proxy: bool,
+    frames: FrameScope,
+) -> Result<SinksResult> {
+    let PollutionFindings {
+        sinks,
+        proxy_accesses,
+    } = collect_pollution_findings(crawler, url, properties, use_proxy, frames).await?;
 
-    let relevant_log_entries = retain_sink_related_log_entries(&log);
+    info!("found {} relevant log entries", sinks.len());
+    for sink in &sinks {
+        match sink {
+            SinkEntry::SinkReached { sink_type, .. } => {
+                info!(sink_type = sink_type, "sink reached")
+            }
+            SinkEntry::AssignTaintedKey {
+                class_name, key, ..
+            } => info!(class_name = class_name, key = key, "tainted key assigned"),
+        }
+    }
+
+    let count = sinks.len();
+    Ok(SinksResult {
+        url: url.to_string(),
+        polluted_properties: properties
+            .iter()
+            .map(|(name, value)| PollutedProperty {
+                name: name.clone(),
+                value: value.clone(),
+            })
+            .collect(),
+        sinks,
+        count,
+        proxy_accesses,
+    })
+}
 
-    info!("found {} relevant log entries", relevant_log_entries.len());
-    for entry in relevant_log_entries {
-        info!("log entry {:#?}", entry);
+fn is_sink_related(msg: &LogMessage) -> bool {
+    match msg {
+        LogMessage::AssignTaintedKey { class_name, .. } => {
+            !matches!(class_name.as_str(), "Object" | "Array" | "Function")
+        }
+        LogMessage::SinkReached { .. } => true,
+        _ => false,
     }
+}
 
-    Ok(())
+/// A document split from a log, along with the frames to report its records in
+struct SelectedDocument<'a> {
+    document: DocumentLog<'a>,
+    /// Frame of the document, if it is in the frame scope
+    frame: Option<FrameInfo>,
+    /// Frame of the records whose document is only inferred, which only has the
+    /// log file
+    unattributed: FrameInfo,
 }
 
-fn retain_sink_related_log_entries(log: &SiteLog) -> Vec<&LogMessage> {
-    log.messages
-        .iter()
-        .filter(|msg| match msg {
-            LogMessage::AssignTaintedKey { class_name, .. } => {
-                !matches!(class_name.as_str(), "Object" | "Array" | "Function")
-            }
-            LogMessage::SinkReached { .. } => true,
-            _ => false,
-        })
-        .collect()
+impl<'a> SelectedDocument<'a> {
+    /// Records of the document to analyze, with the frame they were logged in.
+    ///
+    /// Records whose document is only inferred may come from any frame, so they
+    /// are kept whatever the frame scope, without frame information.
+    fn records(&self) -> impl Iterator<Item = (&'a LogMessage, &FrameInfo)> + '_ {
+        let document = &self.document;
+        document
+            .messages
+            .iter()
+            .zip(&document.inferred)
+            .filter_map(|(&msg, &inferred)| match (&self.frame, inferred) {
+                (_, true) => Some((msg, &self.unattributed)),
+                (Some(frame), false) => Some((msg, frame)),
+                (None, false) => None,
+            })
+    }
+}
+
+/// Split the log into documents, along with their frame information for the ones
+/// in the given scope. Documents out of scope are kept for the records that may
+/// come from another document, see [`SelectedDocument::records`].
+///
+/// Logs without frame information are assumed to start with the document of the
+/// top frame, and the origin of later top frame documents is unknown.
+fn select_documents(log: &SiteLog, frames: FrameScope) -> Vec<SelectedDocument<'_>> {
+    let mut selected = Vec::new();
+    let mut top_origin = None;
+    let mut seen_document = false;
+    for document in log.documents() {
+        let is_top_frame = match document.is_top_frame {
+            None if document.started && !seen_document => Some(true),
+            is_top_frame => is_top_frame,
+        };
+        seen_document |= document.started;
+
+        let origin = document.url.and_then(url_origin);
+        if is_top_frame == Some(true) {
+            top_origin.clone_from(&origin);
+        }
+
+        let in_scope = match frames {
+            FrameScope::All => true,
+            FrameScope::Top => is_top_frame == Some(true),
+            FrameScope::SameOrigin => origin.is_some() && origin == top_origin,
+        };
+        let log_file = document.log_file.map(Path::to_path_buf);
+        let frame = in_scope.then(|| FrameInfo {
+            log_file: log_file.clone(),
+            url: document.url.map(str::to_owned),
+            origin,
+            is_top_frame,
+        });
+        selected.push(SelectedDocument {
+            document,
+            frame,
+            unattributed: FrameInfo {
+                log_file,
+                ..FrameInfo::default()
+            },
+        });
+    }
+
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(s: &str) -> (String, PollutionValue) {
+        Commands::parse_custom_property(s).unwrap()
+    }
+
+    #[test]
+    fn custom_property_marker() {
+        let (key, value) = parse("innerHTML");
+        assert_eq!(key, "innerHTML");
+        assert!(matches!(value, PollutionValue::Marker));
+
+        assert!(matches!(parse("src:string").1, PollutionValue::Marker));
+    }
+
+    #[test]
+    fn custom_property_typed_values() {
+        let (key, value) = parse("html=<b>bold</b>");
+        assert_eq!(key, "html");
+        assert!(matches!(value, PollutionValue::Value(v) if v == json!("<b>bold</b>")));
+
+        assert!(matches!(parse("depth:number=3").1, PollutionValue::Value(v) if v == json!(3)));
+        assert!(matches!(parse("debug:bool=true").1, PollutionValue::Value(v) if v == json!(true)));
+        assert!(matches!(
+            parse("config:json={\"a\":[1]}").1,
+            PollutionValue::Value(v) if v == json!({ "a": [1] })
+        ));
+    }
+
+    #[test]
+    fn custom_property_tainted_containers() {
+        assert!(matches!(
+            parse("list:array").1,
+            PollutionValue::TaintedArray
+        ));
+        assert!(matches!(
+            parse("options:object").1,
+            PollutionValue::TaintedObject
+        ));
+        assert!(matches!(
+            parse("callback:fn").1,
+            PollutionValue::Function(None)
+        ));
+        assert!(matches!(
+            parse("callback:function=42").1,
+            PollutionValue::Function(Some(v)) if v == json!(42)
+        ));
+    }
+
+    #[test]
+    fn custom_property_key_with_colon() {
+        let (key, value) = parse("xlink:href");
+        assert_eq!(key, "xlink:href");
+        assert!(matches!(value, PollutionValue::Marker));
+
+        let (key, _) = parse("a:b:number=1");
+        assert_eq!(key, "a:b");
+    }
+
+    #[test]
+    fn custom_property_invalid() {
+        assert!(Commands::parse_custom_property("depth:number").is_err());
+        assert!(Commands::parse_custom_property("depth:number=\"3\"").is_err());
+        assert!(Commands::parse_custom_property("list:array={}").is_err());
+        assert!(Commands::parse_custom_property("config:json={").is_err());
+    }
+
+    fn prototype_get(key: &str, marker: &str, line: u32) -> LogMessage {
+        LogMessage::PrototypeGet {
+            key: key.to_owned(),
+            value: marker.to_owned(),
+            stack_trace: StackTrace::new(format!(
+                "Error\n    at Object.get (<anonymous>:23:15)\n    at render (https://example.com/app.js:{line}:7)"
+            )),
+        }
+    }
+
+    #[test]
+    fn taint_marker_matches_its_read() {
+        let messages = [
+            prototype_get("innerHTML", "0xEFFACED:0:0", 3),
+            prototype_get("src", "0xEFFACED:1:0", 9),
+        ];
+        let messages = messages.iter().collect::<Vec<_>>();
+        let prototype_gets = index_prototype_gets(&messages);
+        let value = "<img src=x onerror=0xEFFACED:1:0>".to_owned();
+
+        let sources = find_taint_sources(&[&value], &prototype_gets);
+        assert_eq!(sources.len(), 1);
+        let source = &sources[0];
+        assert_eq!(source.marker, "0xEFFACED:1:0");
+        assert_eq!((source.property_index, source.access_index), (1, 0));
+        assert_eq!(source.property.as_deref(), Some("src"));
+        let location = source.location.as_ref().unwrap();
+        assert_eq!(location.filepath, "example.com/app.js");
+        assert_eq!((location.line, location.column), (9, 7));
+        assert!(source.stack_trace.is_some());
+    }
+
+    #[test]
+    fn taint_marker_with_unknown_index() {
+        let messages = [prototype_get("innerHTML", "0xEFFACED:0:0", 3)];
+        let messages = messages.iter().collect::<Vec<_>>();
+        let prototype_gets = index_prototype_gets(&messages);
+        let value = "0xEFFACED:2:5".to_owned();
+
+        let sources = find_taint_sources(&[&value], &prototype_gets);
+        assert_eq!(sources.len(), 1);
+        let source = &sources[0];
+        assert_eq!((source.property_index, source.access_index), (2, 5));
+        assert_eq!(source.property, None);
+        assert!(source.location.is_none() && source.stack_trace.is_none());
+
+        let value = "0xEFFACED:x:0 0xEFFACED".to_owned();
+        assert!(find_taint_sources(&[&value], &prototype_gets).is_empty());
+    }
+
+    #[test]
+    fn taint_markers_of_several_accesses() {
+        let messages = [
+            prototype_get("innerHTML", "0xEFFACED:0:0", 3),
+            prototype_get("innerHTML", "0xEFFACED:0:1", 4),
+            prototype_get("innerHTML", "0xEFFACED:0:2", 5),
+        ];
+        let messages = messages.iter().collect::<Vec<_>>();
+        let prototype_gets = index_prototype_gets(&messages);
+        let first = "0xEFFACED:0:2 and 0xEFFACED:0:0".to_owned();
+        let second = "0xEFFACED:0:2".to_owned();
+
+        let sources = find_taint_sources(&[&first, &second], &prototype_gets);
+        let reads = sources
+            .iter()
+            .map(|source| {
+                let location = source.location.as_ref().unwrap();
+                (source.access_index, location.line)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(reads, [(0, 3), (2, 5)]);
+        assert!(sources
+            .iter()
+            .all(|source| source.property.as_deref() == Some("innerHTML")));
+    }
+
+    #[test]
+    fn renderer_records_after_an_iframe_starts_are_unattributed() {
+        let in_document = |id: &str| LogMessage::InDocument { id: id.to_owned() };
+        let undefined = |name: &str| LogMessage::UndefinedProperty {
+            name: name.to_owned(),
+            source: log_parser::PropertySource::InlineCache,
+            stack_trace: StackTrace::new(String::new()),
+        };
+        let log = SiteLog {
+            messages: vec![
+                in_document("top"),
+                LogMessage::DocumentStart {
+                    url: Some("https://example.com/".to_owned()),
+                    is_top_frame: Some(true),
+                },
+                undefined("before"),
+                in_document("frame"),
+                LogMessage::DocumentStart {
+                    url: Some("about:blank".to_owned()),
+                    is_top_frame: Some(false),
+                },
+                // logged for the top document, but may as well come from the iframe
+                undefined("after"),
+            ],
+            diagnostics: Vec::new(),
+            log_files: Vec::new(),
+        };
+
+        for frames in [FrameScope::All, FrameScope::Top, FrameScope::SameOrigin] {
+            let properties = find_undefined_properties_in_log(&log, frames);
+            let frames = properties
+                .iter()
+                .map(|property| (property.name.as_str(), property.frame.url.as_deref()))
+                .collect::<BTreeMap<_, _>>();
+            assert_eq!(
+                frames,
+                BTreeMap::from([("after", None), ("before", Some("https://example.com/"))])
+            );
+        }
+    }
+
+    #[test]
+    fn crashes_are_retried_by_default() {
+        let cli = Cli::parse_from([
+            "ghunter4chrome",
+            "--url",
+            "https://example.com/",
+            "undefined",
+        ]);
+        let policy = cli.retry_policy();
+        assert_eq!(policy.retries, 0);
+        assert!(policy.retries_crash(0));
+        assert!(!policy.retries_crash(1));
+
+        let cli = Cli::parse_from([
+            "ghunter4chrome",
+            "--url",
+            "https://example.com/",
+            "--retries",
+            "3",
+            "--crash-retries",
+            "0",
+            "undefined",
+        ]);
+        assert!(!cli.retry_policy().retries_crash(0));
+    }
+
+    #[test]
+    fn analyze_saved_logs() {
+        use ghunter4chromium_gadget_finder::log_writer::LogWriter;
+
+        let trace = |line: u32| {
+            StackTrace::new(format!(
+                "Error\n    at render (https://example.com/app.js:{line}:7)"
+            ))
+        };
+        let messages = [
+            LogMessage::DocumentStart {
+                url: Some("https://example.com/".to_owned()),
+                is_top_frame: Some(true),
+            },
+            LogMessage::UndefinedProperty {
+                name: "gadget".to_owned(),
+                source: log_parser::PropertySource::InlineCache,
+                stack_trace: trace(3),
+            },
+            LogMessage::PrototypeGet {
+                key: "gadget".to_owned(),
+                value: "0xEFFACED:0:0".to_owned(),
+                stack_trace: trace(4),
+            },
+            LogMessage::SinkReached {
+                sink_type: "innerHTML".to_owned(),
+                value: "<b>0xEFFACED:0:0</b>".to_owned(),
+                stack_trace: trace(5),
+            },
+            LogMessage::Unknown {
+                name: "Future".to_owned(),
+                subtype: None,
+                raw: "record".to_owned(),
+            },
+        ];
+        let dir = TempDir::new("ghunter4chrome-test").unwrap();
+        let log_file = dir.path().join("renderer.log");
+        let mut writer = LogWriter::new(File::create(&log_file).unwrap());
+        for message in &messages {
+            writer.write(message).unwrap();
+        }
+        drop(writer);
+        let missing_file = dir.path().join("missing.log");
+        let output_json = dir.path().join("output.json");
+
+        analyze_log_files(
+            &[log_file.clone(), missing_file.clone()],
+            &output_json,
+            OutputFormat::Json,
+            false,
+            FrameScope::All,
+            None,
+        )
+        .unwrap();
+
+        let output: Value =
+            serde_json::from_str(&std::fs::read_to_string(&output_json).unwrap()).unwrap();
+        let reports = output.as_array().unwrap();
+        assert_eq!(reports.len(), 2);
+
+        let report = &reports[0];
+        assert_eq!(report["log_file"], json!(log_file));
+        assert!(report["error"].is_null());
+        let result = &report["result"];
+        assert_eq!(result["skipped_records"], 0);
+        let undefined = result["undefined_properties"].as_array().unwrap();
+        assert_eq!(undefined.len(), 1);
+        assert_eq!(undefined[0]["name"], "gadget");
+        assert_eq!(undefined[0]["location"]["line"], 3);
+        assert_eq!(undefined[0]["frame"]["is_top_frame"], true);
+        let sinks = result["sinks"].as_array().unwrap();
+        assert_eq!(sinks.len(), 1);
+        assert_eq!(sinks[0]["sink_type"], "innerHTML");
+        assert_eq!(sinks[0]["location"]["line"], 5);
+        assert_eq!(sinks[0]["sources"][0]["property"], "gadget");
+        assert_eq!(sinks[0]["sources"][0]["location"]["line"], 4);
+        assert_eq!(result["unknown_records"][0]["name"], "Future");
+
+        let report = &reports[1];
+        assert_eq!(report["log_file"], json!(missing_file));
+        assert!(report["result"].is_null());
+        assert!(report["error"].is_string());
+    }
 }
@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::log_parser::{LogMessage, SiteLog};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum OutputFormat {
+    /// A single JSON array, with each element written as soon as its URL is processed.
+    #[default]
+    Json,
+    /// One JSON object per line, written as soon as each URL is processed.
+    JsonLines,
+}
+
+/// Collects the per-URL results of a run into a single output document.
+pub enum OutputWriter<W: Write = BufWriter<File>> {
+    Json {
+        writer: W,
+        /// No element has been written yet, so the next one needs no separator
+        empty: bool,
+    },
+    JsonLines {
+        writer: W,
+    },
+}
+
+impl OutputWriter {
+    pub fn create(path: &Path, format: OutputFormat) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create output file at {:?}", path))?;
+        Self::new(BufWriter::new(file), format)
+    }
+}
+
+impl<W: Write> OutputWriter<W> {
+    pub fn new(mut writer: W, format: OutputFormat) -> Result<Self> {
+        match format {
+            OutputFormat::Json => {
+                writer.write_all(b"[")?;
+                Ok(Self::Json {
+                    writer,
+                    empty: true,
+                })
+            }
+            OutputFormat::JsonLines => Ok(Self::JsonLines { writer }),
+        }
+    }
+
+    /// Append a record to the output. The record is flushed immediately so that
+    /// an interrupted run keeps everything processed so far (in JSON mode, only the
+    /// closing bracket of the array is then missing).
+    pub fn write(&mut self, record: &impl Serialize) -> Result<()> {
+        match self {
+            Self::Json { writer, empty } => {
+                writer.write_all(if *empty { b"\n" } else { b",\n" })?;
+                *empty = false;
+                serde_json::to_writer_pretty(&mut *writer, record)?;
+                writer.flush()?;
+            }
+            Self::JsonLines { writer } => {
+                serde_json::to_writer(&mut *writer, record)?;
+                writer.write_all(b"\n")?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Self::Json { mut writer, empty } => {
+                writer.write_all(if empty { b"]" } else { b"\n]" })?;
+                writer.flush()?;
+            }
+            Self::JsonLines { mut writer } => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+/// Writes every record of the parsed logs as JSON Lines, tagged with where the log
+/// comes from. Can be cloned to be shared between crawlers.
+#[derive(Clone)]
+pub struct LogDumper {
+    writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+#[derive(Serialize)]
+struct DumpedRecord<'a> {
+    /// The visited URL, or the path of the analyzed log file
+    origin: &'a str,
+    #[serde(flatten)]
+    message: &'a LogMessage,
+}
+
+impl LogDumper {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create log dump file at {:?}", path))?;
+        Ok(Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    pub fn dump(&self, origin: &str, log: &SiteLog) -> Result<()> {
+        let mut writer = self.lock()?;
+        for message in &log.messages {
+            serde_json::to_writer(&mut *writer, &DumpedRecord { origin, message })?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        let mut writer = self.lock()?;
+        writer.flush().context("failed to write log dump file")
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, BufWriter<File>>> {
+        self.writer
+            .lock()
+            .map_err(|_| anyhow!("log dump writer lock poisoned"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    use crate::{log_parser::PropertySource, stack_trace::StackTrace};
+
+    fn write_output(format: OutputFormat, records: &[Value]) -> String {
+        let mut output = Vec::new();
+        let mut writer = OutputWriter::new(&mut output, format).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn json_array() {
+        let output = write_output(OutputFormat::Json, &[]);
+        assert_eq!(output, "[]");
+        assert_eq!(serde_json::from_str::<Value>(&output).unwrap(), json!([]));
+
+        let records = [
+            json!({ "url": "https://a.example/" }),
+            json!({ "url": "https://b.example/", "sinks": [1, 2] }),
+        ];
+        let output = write_output(OutputFormat::Json, &records[..1]);
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!(records[..1])
+        );
+        let output = write_output(OutputFormat::Json, &records);
+        assert!(
+            output.starts_with("[\n{") && output.ends_with("}\n]"),
+            "{output}"
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(&output).unwrap(),
+            json!(records)
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        assert_eq!(write_output(OutputFormat::JsonLines, &[]), "");
+
+        let records = [
+            json!({ "url": "https://a.example/" }),
+            json!({ "url": "https://b.example/", "sinks": [1, 2] }),
+        ];
+        let output = write_output(OutputFormat::JsonLines, &records);
+        let lines = output
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, records);
+        assert!(output.ends_with('\n'));
+    }
+
+    #[test]
+    fn dumped_records() {
+        let dir = tempdir::TempDir::new("ghunter4chrome-test").unwrap();
+        let path = dir.path().join("dump.jsonl");
+        let log = SiteLog {
+            messages: vec![
+                LogMessage::Location {
+                    url: "https://example.com/".to_owned(),
+                },
+                LogMessage::UndefinedProperty {
+                    name: "foo".to_owned(),
+                    source: PropertySource::InlineCache,
+                    stack_trace: StackTrace::new("at f (https://example.com/a.js:1:2)".to_owned()),
+                },
+            ],
+            diagnostics: Vec::new(),
+            log_files: Vec::new(),
+        };
+
+        let dumper = LogDumper::create(&path).unwrap();
+        dumper.dump("https://example.com/", &log).unwrap();
+        dumper.clone().dump("other.log", &log).unwrap();
+        dumper.flush().unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records = contents
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 4);
+        assert_eq!(
+            records[0],
+            json!({ "origin": "https://example.com/", "type": "location", "url": "https://example.com/" })
+        );
+        let origins = [
+            "https://example.com/",
+            "https://example.com/",
+            "other.log",
+            "other.log",
+        ];
+        for ((record, origin), message) in
+            records.iter().zip(origins).zip(log.messages.iter().cycle())
+        {
+            let mut expected = serde_json::to_value(message).unwrap();
+            expected["origin"] = json!(origin);
+            assert_eq!(*record, expected);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chromiumoxide::cdp::browser_protocol::{
+    fetch::HeaderEntry,
+    network::{CookieParam, CookieSameSite, TimeSinceEpoch},
+};
+use serde::Deserialize;
+use url::Url;
+
+/// Cookies and extra HTTP headers sent by the crawler on every visit, e.g.,
+/// to scan pages that are only available once logged in
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    pub cookies: Vec<CookieParam>,
+    pub headers: Vec<HeaderRule>,
+}
+
+impl Session {
+    /// Headers to add to a request to the given URL
+    pub fn headers_for(&self, url: &str) -> Vec<HeaderEntry> {
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+        else {
+            return Vec::new();
+        };
+
+        self.headers
+            .iter()
+            .filter(|rule| rule.matches(&host))
+            .map(|rule| HeaderEntry::new(&rule.name, &rule.value))
+            .collect()
+    }
+}
+
+/// An HTTP header to send with every request to a domain and its subdomains
+#[derive(Clone, Debug)]
+pub struct HeaderRule {
+    /// `*` matches every domain
+    pub domain: String,
+    pub name: String,
+    pub value: String,
+}
+
+impl HeaderRule {
+    /// Parse a rule given as `DOMAIN:NAME: VALUE`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (domain, header) = s
+            .split_once(':')
+            .ok_or_else(|| "expected DOMAIN:NAME: VALUE".to_owned())?;
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| "expected a header as NAME: VALUE".to_owned())?;
+        let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+        let name = name.trim();
+        if domain.is_empty() || name.is_empty() {
+            return Err("the domain and the header name must not be empty".to_owned());
+        }
+
+        Ok(Self {
+            domain,
+            name: name.to_owned(),
+            value: value.trim().to_owned(),
+        })
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.domain == "*"
+            || host
+                .strip_suffix(&self.domain)
+                .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with('.'))
+    }
+}
+
+/// Load the cookies of a cookie jar, either in the Netscape format (as written
+/// by curl or wget) or in JSON (as exported by browser extensions, Puppeteer or
+/// Playwright)
+pub fn load_cookie_jar(path: &Path) -> Result<Vec<CookieParam>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read cookie jar at {:?}", path))?;
+
+    let cookies = if contents.trim_start().starts_with(['[', '{']) {
+        parse_json_cookies(&contents)
+    } else {
+        parse_netscape_cookies(&contents)
+    };
+    cookies.with_context(|| format!("failed to parse cookie jar at {:?}", path))
+}
+
+/// Cookie of a JSON cookie jar. The field names of the DevTools protocol are
+/// used, with aliases for those of the common browser extensions.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonCookie {
+    name: String,
+    value: String,
+    domain: String,
+    #[serde(default)]
+    path: Option<String>,
+    #[serde(default, alias = "expirationDate")]
+    expires: Option<f64>,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    http_only: bool,
+    /// Without this field, cookies are host-only unless their domain starts with a dot
+    #[serde(default)]
+    host_only: Option<bool>,
+    #[serde(default)]
+    same_site: Option<String>,
+}
+
+/// Playwright's storage state, which holds the cookies along with local storage
+#[derive(Deserialize)]
+struct StorageState {
+    cookies: Vec<JsonCookie>,
+}
+
+/// Parse a JSON cookie jar, either an array of cookies or a storage state object
+fn parse_json_cookies(contents: &str) -> Result<Vec<CookieParam>> {
+    let cookies = if contents.trim_start().starts_with('[') {
+        serde_json::from_str::<Vec<JsonCookie>>(contents).context("invalid array of cookies")?
+    } else {
+        serde_json::from_str::<StorageState>(contents)
+            .context("invalid storage state")?
+            .cookies
+    };
+
+    Ok(cookies
+        .into_iter()
+        .map(|cookie| {
+            let same_site = cookie.same_site.as_deref().and_then(|same_site| {
+                match same_site.to_ascii_lowercase().as_str() {
+                    "strict" => Some(CookieSameSite::Strict),
+                    "lax" => Some(CookieSameSite::Lax),
+                    "none" | "no_restriction" => Some(CookieSameSite::None),
+                    _ => None,
+                }
+            });
+            let mut param = cookie_param(
+                cookie.name,
+                cookie.value,
+                &cookie.domain,
+                match cookie.host_only {
+                    Some(host_only) => !host_only,
+                    None => cookie.domain.starts_with('.'),
+                },
+                cookie.path.as_deref().unwrap_or("/"),
+                cookie.secure,
+            );
+            param.http_only = Some(cookie.http_only);
+            param.expires = cookie
+                .expires
+                .filter(|expires| *expires > 0.0)
+                .map(TimeSinceEpoch::new);
+            param.same_site = same_site;
+            param
+        })
+        .collect())
+}
+
+/// Parse a Netscape cookie jar, made of lines with 7 tab-separated fields:
+/// domain, include subdomains, path, secure, expiry, name and value
+fn parse_netscape_cookies(contents: &str) -> Result<Vec<CookieParam>> {
+    let mut cookies = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        // curl marks HttpOnly cookies with a prefix that makes them look like comments
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, include_subdomains, path, secure, expires, name, value] = fields[..] else {
+            bail!(
+                "line {}: expected 7 tab-separated fields, found {}",
+                i + 1,
+                fields.len()
+            );
+        };
+        let expires: f64 = expires
+            .parse()
+            .with_context(|| format!("line {}: invalid expiry {:?}", i + 1, expires))?;
+
+        let mut param = cookie_param(
+            name.to_owned(),
+            value.to_owned(),
+            domain,
+            include_subdomains.eq_ignore_ascii_case("TRUE"),
+            path,
+            secure.eq_ignore_ascii_case("TRUE"),
+        );
+        param.http_only = Some(http_only);
+        // an expiry of 0 is a session cookie
+        if expires > 0.0 {
+            param.expires = Some(TimeSinceEpoch::new(expires));
+        }
+        cookies.push(param);
+    }
+    Ok(cookies)
+}
+
+fn cookie_param(
+    name: String,
+    value: String,
+    domain: &str,
+    include_subdomains: bool,
+    path: &str,
+    secure: bool,
+) -> CookieParam {
+    let host = domain.trim_start_matches('.');
+    let mut param = CookieParam::new(name, value);
+    // cookies set with a domain are sent to subdomains as well, so host-only
+    // cookies are set for the URL of their host instead
+    if include_subdomains {
+        param.domain = Some(format!(".{}", host));
+    } else {
+        let scheme = if secure { "https" } else { "http" };
+        param.url = Some(format!("{}://{}{}", scheme, host, path));
+    }
+    param.path = Some(path.to_owned());
+    param.secure = Some(secure);
+    param
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_rule_parse() {
+        let rule = HeaderRule::parse(".Example.com:Authorization: Bearer a:b").unwrap();
+        assert_eq!(rule.domain, "example.com");
+        assert_eq!(rule.name, "Authorization");
+        assert_eq!(rule.value, "Bearer a:b");
+
+        let rule = HeaderRule::parse("*:X-Empty:").unwrap();
+        assert_eq!(rule.domain, "*");
+        assert_eq!(rule.value, "");
+
+        assert!(HeaderRule::parse("example.com").is_err());
+        assert!(HeaderRule::parse("example.com:X-Missing-Value").is_err());
+        assert!(HeaderRule::parse(":X-Header: value").is_err());
+        assert!(HeaderRule::parse("example.com:: value").is_err());
+    }
+
+    #[test]
+    fn header_rule_matches() {
+        let rule = HeaderRule::parse("example.com:X-Test: 1").unwrap();
+        assert!(rule.matches("example.com"));
+        assert!(rule.matches("www.example.com"));
+        assert!(!rule.matches("badexample.com"));
+        assert!(!rule.matches("example.com.evil.net"));
+        assert!(HeaderRule::parse("*:X-Test: 1")
+            .unwrap()
+            .matches("anything.org"));
+    }
+
+    #[test]
+    fn headers_for_url() {
+        let session = Session {
+            cookies: Vec::new(),
+            headers: vec![
+                HeaderRule::parse("example.com:X-A: a").unwrap(),
+                HeaderRule::parse("other.com:X-B: b").unwrap(),
+            ],
+        };
+        let headers = session.headers_for("https://sub.example.com/page");
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[0].name, "X-A");
+        assert!(session.headers_for("not a url").is_empty());
+    }
+
+    #[test]
+    fn netscape_cookies() {
+        let jar = "# Netscape HTTP Cookie File\n\
+            \n\
+            .example.com\tTRUE\t/\tTRUE\t1893456000\tsession\tabc\n\
+            #HttpOnly_app.example.com\tFALSE\t/app\tFALSE\t0\ttoken\tx=y\n";
+        let cookies = parse_netscape_cookies(jar).unwrap();
+        assert_eq!(cookies.len(), 2);
+
+        assert_eq!(cookies[0].name, "session");
+        assert_eq!(cookies[0].value, "abc");
+        assert_eq!(cookies[0].domain.as_deref(), Some(".example.com"));
+        assert_eq!(cookies[0].url, None);
+        assert_eq!(cookies[0].secure, Some(true));
+        assert_eq!(cookies[0].http_only, Some(false));
+        assert!(cookies[0].expires.is_some());
+
+        assert_eq!(cookies[1].name, "token");
+        assert_eq!(cookies[1].value, "x=y");
+        assert_eq!(cookies[1].domain, None);
+        assert_eq!(
+            cookies[1].url.as_deref(),
+            Some("http://app.example.com/app")
+        );
+        assert_eq!(cookies[1].path.as_deref(), Some("/app"));
+        assert_eq!(cookies[1].http_only, Some(true));
+        assert!(cookies[1].expires.is_none());
+    }
+
+    #[test]
+    fn netscape_cookies_invalid() {
+        let error = parse_netscape_cookies("example.com\tFALSE\t/\n").unwrap_err();
+        assert!(error.to_string().contains("line 1"));
+        let error =
+            parse_netscape_cookies("\nexample.com\tFALSE\t/\tFALSE\tsoon\ta\tb\n").unwrap_err();
+        assert!(error.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn json_cookie_array() {
+        let jar = r#"[
+            {"name": "a", "value": "1", "domain": ".example.com", "path": "/",
+             "expirationDate": 1893456000.5, "secure": true, "httpOnly": true,
+             "hostOnly": false, "sameSite": "no_restriction"},
+            {"name": "b", "value": "2", "domain": "example.com"}
+        ]"#;
+        let cookies = parse_json_cookies(jar).unwrap();
+        assert_eq!(cookies.len(), 2);
+
+        assert_eq!(cookies[0].domain.as_deref(), Some(".example.com"));
+        assert_eq!(cookies[0].http_only, Some(true));
+        assert_eq!(cookies[0].same_site, Some(CookieSameSite::None));
+        assert!(cookies[0].expires.is_some());
+
+        assert_eq!(cookies[1].url.as_deref(), Some("http://example.com/"));
+        assert_eq!(cookies[1].secure, Some(false));
+        assert_eq!(cookies[1].same_site, None);
+    }
+
+    #[test]
+    fn json_storage_state() {
+        let state = r#"{
+            "cookies": [{"name": "a", "value": "1", "domain": "example.com", "path": "/",
+                         "expires": -1, "httpOnly": false, "secure": true, "sameSite": "Lax"}],
+            "origins": []
+        }"#;
+        let cookies = parse_json_cookies(state).unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(cookies[0].url.as_deref(), Some("https://example.com/"));
+        assert_eq!(cookies[0].same_site, Some(CookieSameSite::Lax));
+        assert!(cookies[0].expires.is_none());
+    }
+
+    #[test]
+    fn json_cookies_report_their_format() {
+        let error = parse_json_cookies(r#"[{"name": "a"}]"#).unwrap_err();
+        assert_eq!(error.to_string(), "invalid array of cookies");
+        let error = parse_json_cookies(r#"{"origins": []}"#).unwrap_err();
+        assert_eq!(error.to_string(), "invalid storage state");
+    }
+}
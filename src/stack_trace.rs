@@ -0,0 +1,333 @@
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+
+/// How many nested eval origins to look through to find the script of an eval frame
+const MAX_EVAL_DEPTH: usize = 16;
+
+/// A V8 stack trace, as logged by ghunter.
+///
+/// Only the raw stack trace is kept, as most of them are never looked at: its
+/// frames are parsed on demand, borrowing from it. When serialized, the parsed
+/// frames are included along with the raw stack trace.
+///
+/// Like [`LogMessage`](crate::log_parser::LogMessage), the string is owned by
+/// default but can be borrowed from the log.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StackTrace<S = String> {
+    pub raw: S,
+}
+
+/// A single `at ...` line of a V8 stack trace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StackFrame<S = String> {
+    /// Name of the function, including the receiver type and `new ` if present,
+    /// e.g., `HTMLDivElement.set innerHTML`
+    pub function_name: Option<S>,
+    /// URL of the script. For eval frames, this is the script that called eval.
+    pub script_url: Option<S>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// The code of this frame was created through `eval` (or `Function`)
+    pub is_eval: bool,
+    /// The frame is a builtin implemented natively
+    pub is_native: bool,
+    /// The frame is an `await` point of an async function
+    pub is_async: bool,
+    /// The function has no name or the script has no URL (`<anonymous>`)
+    pub is_anonymous: bool,
+}
+
+impl<S> StackTrace<S> {
+    pub fn new(raw: S) -> Self {
+        Self { raw }
+    }
+
+    /// Convert the string of this stack trace with the given function
+    pub fn map_str<T>(self, f: impl FnOnce(S) -> T) -> StackTrace<T> {
+        StackTrace { raw: f(self.raw) }
+    }
+}
+
+impl<S: AsRef<str>> StackTrace<S> {
+    /// Parse the frames of the stack trace in the V8 format, ignoring any line that
+    /// is not a frame (such as the `Error` header of traces created from JavaScript)
+    pub fn frames(&self) -> impl Iterator<Item = StackFrame<&str>> {
+        self.raw
+            .as_ref()
+            .lines()
+            .filter_map(|line| line.trim_start().strip_prefix("at "))
+            .map(StackFrame::parse)
+    }
+
+    /// The first frame pointing to a script with a URL, which skips builtins and
+    /// scripts injected through the DevTools protocol (such as the polluting script)
+    pub fn top_script_frame(&self) -> Option<StackFrame<&str>> {
+        self.frames().find(|frame| {
+            frame.line.is_some() && frame.script_url.is_some_and(|url| !url.is_empty())
+        })
+    }
+}
+
+impl<S: AsRef<str>> Serialize for StackTrace<S> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let mut state = serializer.serialize_struct("StackTrace", 2)?;
+        state.serialize_field("raw", self.raw.as_ref())?;
+        state.serialize_field("frames", &self.frames().collect::<Vec<_>>())?;
+        state.end()
+    }
+}
+
+impl<'a> StackFrame<&'a str> {
+    /// Parse a frame, without the leading `at `
+    fn parse(frame: &'a str) -> Self {
+        let frame = frame.trim_end();
+        let (frame, is_async) = match frame.strip_prefix("async ") {
+            Some(frame) => (frame, true),
+            None => (frame, false),
+        };
+
+        // either `function (location)` or just `location`
+        let (function_name, location) = match split_parenthesized_suffix(frame) {
+            Some((function_name, location)) => (Some(function_name), location),
+            None => (None, frame),
+        };
+        let function_name = function_name.filter(|name| !name.is_empty());
+
+        let mut parsed = Self {
+            function_name,
+            script_url: None,
+            line: None,
+            column: None,
+            is_eval: false,
+            is_native: false,
+            is_async,
+            is_anonymous: function_name.is_none_or(|name| name == "<anonymous>"),
+        };
+        parsed.parse_location(location);
+        parsed
+    }
+
+    fn parse_location(&mut self, mut location: &'a str) {
+        // e.g., `eval at foo (https://example.com/a.js:1:2), <anonymous>:3:4`,
+        // where the origin might itself be an eval frame
+        let mut depth = 0;
+        while let Some(eval_origin) = location.strip_prefix("eval at ") {
+            self.is_eval = true;
+            depth += 1;
+            if depth > MAX_EVAL_DEPTH {
+                return;
+            }
+            let eval_origin = match eval_origin.rfind(", ") {
+                Some(i) => &eval_origin[..i],
+                None => eval_origin,
+            };
+            location = match split_parenthesized_suffix(eval_origin) {
+                Some((_, origin_location)) => origin_location,
+                None => eval_origin,
+            };
+        }
+
+        if location == "native" {
+            self.is_native = true;
+            return;
+        }
+
+        let (script_url, line, column) = split_line_column(location);
+        if script_url == "<anonymous>" {
+            self.is_anonymous = true;
+        } else if !script_url.is_empty() {
+            self.script_url = Some(script_url);
+        }
+        self.line = line;
+        self.column = column;
+    }
+}
+
+impl<S> StackFrame<S> {
+    /// Convert every string of this frame with the given function
+    pub fn map_str<T>(self, mut f: impl FnMut(S) -> T) -> StackFrame<T> {
+        StackFrame {
+            function_name: self.function_name.map(&mut f),
+            script_url: self.script_url.map(&mut f),
+            line: self.line,
+            column: self.column,
+            is_eval: self.is_eval,
+            is_native: self.is_native,
+            is_async: self.is_async,
+            is_anonymous: self.is_anonymous,
+        }
+    }
+}
+
+/// Split `prefix (inner)` into `prefix` and `inner`, taking nested parentheses
+/// into account
+fn split_parenthesized_suffix(s: &str) -> Option<(&str, &str)> {
+    let without_paren = s.strip_suffix(')')?;
+    let mut depth = 0;
+    for (i, c) in without_paren.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => {
+                return Some((without_paren[..i].trim_end(), &without_paren[i + 1..]));
+            }
+            '(' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split `url:line:column` (or `url:line`) into its parts
+fn split_line_column(location: &str) -> (&str, Option<u32>, Option<u32>) {
+    let Some((rest, last)) = location.rsplit_once(':') else {
+        return (location, None, None);
+    };
+    let Ok(last) = last.parse() else {
+        return (location, None, None);
+    };
+
+    match rest.rsplit_once(':') {
+        Some((url, line)) => match line.parse() {
+            Ok(line) => (url, Some(line), Some(last)),
+            Err(_) => (rest, Some(last), None),
+        },
+        None => (rest, Some(last), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_frame(line: &str) -> StackFrame<&str> {
+        assert_eq!(StackTrace::new(line).frames().count(), 1, "{line}");
+        StackFrame::parse(line.trim_start().strip_prefix("at ").unwrap())
+    }
+
+    #[test]
+    fn function_with_location() {
+        let frame =
+            parse_frame("    at HTMLDivElement.set innerHTML (https://example.com/a.js:10:5)");
+        assert_eq!(frame.function_name, Some("HTMLDivElement.set innerHTML"));
+        assert_eq!(frame.script_url, Some("https://example.com/a.js"));
+        assert_eq!((frame.line, frame.column), (Some(10), Some(5)));
+        assert!(!frame.is_anonymous && !frame.is_eval && !frame.is_async);
+    }
+
+    #[test]
+    fn location_only() {
+        let frame = parse_frame("at https://example.com/a.js:10:5");
+        assert_eq!(frame.function_name, None);
+        assert!(frame.is_anonymous);
+        assert_eq!(frame.script_url, Some("https://example.com/a.js"));
+        assert_eq!((frame.line, frame.column), (Some(10), Some(5)));
+    }
+
+    #[test]
+    fn url_with_port() {
+        let frame = parse_frame("at init (http://localhost:8080/js/app.js:3:14)");
+        assert_eq!(frame.script_url, Some("http://localhost:8080/js/app.js"));
+        assert_eq!((frame.line, frame.column), (Some(3), Some(14)));
+    }
+
+    #[test]
+    fn line_without_column() {
+        let frame = parse_frame("at https://example.com:8443/a.js:7");
+        assert_eq!(frame.script_url, Some("https://example.com:8443/a.js"));
+        assert_eq!((frame.line, frame.column), (Some(7), None));
+    }
+
+    #[test]
+    fn blob_url() {
+        let frame = parse_frame(
+            "at async run (blob:https://example.com/0b6e4c1a-5b0f-4d8e-9a57-1f6d2c3e4b5a:3:7)",
+        );
+        assert!(frame.is_async);
+        assert_eq!(frame.function_name, Some("run"));
+        assert_eq!(
+            frame.script_url,
+            Some("blob:https://example.com/0b6e4c1a-5b0f-4d8e-9a57-1f6d2c3e4b5a")
+        );
+        assert_eq!((frame.line, frame.column), (Some(3), Some(7)));
+    }
+
+    #[test]
+    fn eval_frame() {
+        let frame =
+            parse_frame("at eval (eval at init (https://example.com/a.js:1:2), <anonymous>:3:4)");
+        assert!(frame.is_eval);
+        assert_eq!(frame.function_name, Some("eval"));
+        assert_eq!(frame.script_url, Some("https://example.com/a.js"));
+        assert_eq!((frame.line, frame.column), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn nested_eval_frame() {
+        let frame = parse_frame(
+            "at eval (eval at a (eval at b (https://example.com/a.js:5:6), <anonymous>:1:2), <anonymous>:3:4)",
+        );
+        assert!(frame.is_eval);
+        assert_eq!(frame.script_url, Some("https://example.com/a.js"));
+        assert_eq!((frame.line, frame.column), (Some(5), Some(6)));
+    }
+
+    #[test]
+    fn deeply_nested_eval_frame() {
+        let mut location = "https://example.com/a.js:1:1".to_owned();
+        for _ in 0..1000 {
+            location = format!("eval at f ({location}), <anonymous>:1:1");
+        }
+        let line = format!("at eval ({location})");
+        let frame = parse_frame(&line);
+        assert!(frame.is_eval);
+        assert_eq!(frame.script_url, None);
+    }
+
+    #[test]
+    fn anonymous_and_native() {
+        let frame = parse_frame("at Array.forEach (<anonymous>)");
+        assert!(frame.is_anonymous);
+        assert_eq!(frame.function_name, Some("Array.forEach"));
+        assert_eq!(frame.script_url, None);
+        assert_eq!(frame.line, None);
+
+        let frame = parse_frame("at <anonymous>:1:15");
+        assert!(frame.is_anonymous);
+        assert_eq!(frame.script_url, None);
+        assert_eq!(frame.line, Some(1));
+
+        let frame = parse_frame("at Math.max (native)");
+        assert!(frame.is_native);
+        assert_eq!(frame.script_url, None);
+    }
+
+    #[test]
+    fn non_ascii_location() {
+        let frame = parse_frame("at f (https://example.com/caf\u{e9}.js:\u{e9}:1)");
+        assert_eq!(frame.line, Some(1));
+        assert_eq!(frame.column, None);
+        let frame = parse_frame("at f (\u{6f22}:\u{5b57})");
+        assert_eq!(frame.line, None);
+    }
+
+    #[test]
+    fn top_script_frame_skips_injected_scripts() {
+        let trace = StackTrace::new(
+            "Error\n    at Object.get (<anonymous>:23:15)\n    at Math.max (native)\n    at render (https://example.com/a.js:1:2)".to_owned(),
+        );
+        assert_eq!(trace.frames().count(), 3);
+        let top = trace.top_script_frame().unwrap();
+        assert_eq!(top.function_name, Some("render"));
+    }
+
+    #[test]
+    fn serialized_with_frames() {
+        let trace = StackTrace::new("Error\n    at f (https://example.com/a.js:1:2)".to_owned());
+        let value = serde_json::to_value(&trace).unwrap();
+        assert_eq!(value["raw"], trace.raw);
+        assert_eq!(value["frames"][0]["function_name"], "f");
+        assert_eq!(value["frames"][0]["line"], 1);
+
+        let deserialized: StackTrace = serde_json::from_value(value).unwrap();
+        assert_eq!(deserialized, trace);
+    }
+}